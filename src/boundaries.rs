@@ -0,0 +1,318 @@
+use bevy::math::Vec2;
+
+use crate::components::BoundaryCondition;
+use crate::defaults::*;
+use crate::kernel::Kernel;
+
+// what happens to material reaching one of the four walls of the domain
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum WallBoundary {
+    Sticky,
+    Slip,
+    Separate { friction: f32 },
+    // particles entering the wall are deleted
+    Outflow,
+    // particles leaving come back in through the opposite wall
+    Periodic,
+}
+
+impl WallBoundary {
+    fn apply(&self, velocity: &mut Vec2, normal: Vec2) {
+        match *self {
+            WallBoundary::Sticky => {
+                constrain_velocity(velocity, normal, Vec2::ZERO, BoundaryCondition::Sticky, 0.)
+            }
+            WallBoundary::Slip => {
+                constrain_velocity(velocity, normal, Vec2::ZERO, BoundaryCondition::Slip, 0.)
+            }
+            WallBoundary::Separate { friction } => constrain_velocity(
+                velocity,
+                normal,
+                Vec2::ZERO,
+                BoundaryCondition::Separate,
+                friction,
+            ),
+            WallBoundary::Outflow | WallBoundary::Periodic => {}
+        }
+    }
+
+    fn is_solid(&self) -> bool {
+        !matches!(self, WallBoundary::Outflow | WallBoundary::Periodic)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Boundaries {
+    pub(super) left: WallBoundary,
+    pub(super) right: WallBoundary,
+    pub(super) bottom: WallBoundary,
+    pub(super) top: WallBoundary,
+    // walls cover this many cells from the edge of the grid
+    pub(super) thickness: usize,
+    // particles heading for a solid wall are slowed down this many seconds before reaching it, 0 to disable
+    pub(super) prediction_time: f32,
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        let wall = WallBoundary::Separate { friction: 0. };
+        Boundaries {
+            left: wall,
+            right: wall,
+            bottom: wall,
+            top: wall,
+            thickness: DEFAULT_WALL_THICKNESS,
+            prediction_time: DEFAULT_WALL_PREDICTION_TIME,
+        }
+    }
+}
+
+impl Boundaries {
+    // grid boundary conditions for the cell at x, y
+    pub(super) fn apply(
+        &self,
+        velocity: &mut Vec2,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        if x < self.thickness {
+            self.left.apply(velocity, Vec2::X);
+        }
+        if x + self.thickness >= width {
+            self.right.apply(velocity, -Vec2::X);
+        }
+        if y < self.thickness {
+            self.bottom.apply(velocity, Vec2::Y);
+        }
+        if y + self.thickness >= height {
+            self.top.apply(velocity, -Vec2::Y);
+        }
+    }
+
+    // periodic walls come in pairs, see the wall selectors in inputs.rs
+    pub(super) fn periodic_x(&self) -> bool {
+        self.left == WallBoundary::Periodic || self.right == WallBoundary::Periodic
+    }
+
+    pub(super) fn periodic_y(&self) -> bool {
+        self.bottom == WallBoundary::Periodic || self.top == WallBoundary::Periodic
+    }
+
+    // keep an advected particle inside the domain, wrapping it around periodic walls.
+    // works in cells, position and velocity are converted from and back to meters.
+    pub(super) fn confine(
+        &self,
+        position: &mut Vec2,
+        velocity: &mut Vec2,
+        width: usize,
+        height: usize,
+        dx: f32,
+        kernel: Kernel,
+    ) {
+        let mut cell_position = *position / dx;
+        let mut cell_velocity = *velocity / dx;
+        self.confine_axis(
+            &mut cell_position.x,
+            &mut cell_velocity.x,
+            self.left,
+            self.right,
+            width,
+            kernel.reach(),
+        );
+        self.confine_axis(
+            &mut cell_position.y,
+            &mut cell_velocity.y,
+            self.bottom,
+            self.top,
+            height,
+            kernel.reach(),
+        );
+        *position = cell_position * dx;
+        *velocity = cell_velocity * dx;
+    }
+
+    fn confine_axis(
+        &self,
+        position: &mut f32,
+        velocity: &mut f32,
+        low: WallBoundary,
+        high: WallBoundary,
+        extent: usize,
+        reach: f32,
+    ) {
+        if low == WallBoundary::Periodic || high == WallBoundary::Periodic {
+            *position = position.rem_euclid(extent as f32);
+            return;
+        }
+
+        // safety clamp so the particle's stencil stays on the grid, outflow walls included.
+        // particles held at the clamp of an outflow wall are deleted there, see flows_out.
+        let last_cell = extent.saturating_sub(1) as f32;
+        let min = reach;
+        let max = last_cell - reach;
+        // on a grid narrower than the stencil the limits cross, keep particles between them
+        *position = position.clamp(max.min(min), max.max(min));
+
+        // predictive boundary velocity cap
+        if self.prediction_time <= 0. {
+            return;
+        }
+        let wall_min = (self.thickness + 1) as f32;
+        let wall_max = last_cell - wall_min;
+        let position_next = *position + *velocity * self.prediction_time;
+        if low.is_solid() && position_next < wall_min {
+            *velocity += wall_min - position_next;
+        }
+        if high.is_solid() && position_next > wall_max {
+            *velocity += wall_max - position_next;
+        }
+    }
+
+    // whether a particle is inside an outflow wall, or held at its clamp when the wall is thinner than the stencil reach
+    pub(super) fn flows_out(
+        &self,
+        position: Vec2,
+        width: usize,
+        height: usize,
+        dx: f32,
+        kernel: Kernel,
+    ) -> bool {
+        let position = position / dx;
+        let reach = kernel.reach();
+        let thickness = self.thickness as f32;
+        let at_low = |p: f32| p < thickness || p <= reach;
+        let at_high = |p: f32, extent: usize| {
+            p >= extent.saturating_sub(self.thickness) as f32
+                || p >= extent.saturating_sub(1) as f32 - reach
+        };
+        (self.left == WallBoundary::Outflow && at_low(position.x))
+            || (self.right == WallBoundary::Outflow && at_high(position.x, width))
+            || (self.bottom == WallBoundary::Outflow && at_low(position.y))
+            || (self.top == WallBoundary::Outflow && at_high(position.y, height))
+    }
+}
+
+// constrain a grid velocity against a surface with an outward normal, moving with surface_velocity.
+pub(super) fn constrain_velocity(
+    velocity: &mut Vec2,
+    normal: Vec2,
+    surface_velocity: Vec2,
+    boundary: BoundaryCondition,
+    friction: f32,
+) {
+    let relative_velocity = *velocity - surface_velocity;
+    let relative_velocity = match boundary {
+        BoundaryCondition::Sticky => Vec2::ZERO,
+        BoundaryCondition::Slip => relative_velocity - normal * relative_velocity.dot(normal),
+        BoundaryCondition::Separate => {
+            let normal_speed = relative_velocity.dot(normal);
+            if normal_speed >= 0.0 {
+                return;
+            }
+            let tangential = relative_velocity - normal * normal_speed;
+            let tangential_speed = tangential.length();
+            if tangential_speed > 0.0 {
+                tangential * (1.0 - f32::min(1.0, -friction * normal_speed / tangential_speed))
+            } else {
+                Vec2::ZERO
+            }
+        }
+    };
+    *velocity = surface_velocity + relative_velocity;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [Kernel; 3] = [Kernel::Linear, Kernel::Quadratic, Kernel::Cubic];
+
+    fn walls(boundary: WallBoundary) -> Boundaries {
+        Boundaries {
+            left: boundary,
+            right: boundary,
+            bottom: boundary,
+            top: boundary,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn periodic_walls_wrap_particles_around() {
+        let boundaries = walls(WallBoundary::Periodic);
+        let mut position = Vec2::new(17.5, -1.) * 0.5;
+        let mut velocity = Vec2::new(10., -10.);
+        boundaries.confine(&mut position, &mut velocity, 16, 16, 0.5, Kernel::Quadratic);
+        assert_eq!(position, Vec2::new(1.5, 15.) * 0.5);
+        assert_eq!(velocity, Vec2::new(10., -10.));
+    }
+
+    #[test]
+    fn outflow_walls_keep_the_stencil_on_the_grid() {
+        let boundaries = walls(WallBoundary::Outflow);
+        for kernel in KERNELS {
+            for outside in [-3., 20.] {
+                let mut position = Vec2::splat(outside);
+                let mut velocity = Vec2::ZERO;
+                boundaries.confine(&mut position, &mut velocity, 16, 16, 1., kernel);
+                let reach = kernel.reach();
+                assert!(
+                    position.cmpge(Vec2::splat(reach)).all()
+                        && position.cmple(Vec2::splat(15. - reach)).all(),
+                    "{} left outside the stencil limits, reach {}",
+                    position,
+                    reach
+                );
+                assert!(
+                    boundaries.flows_out(position, 16, 16, 1., kernel),
+                    "particle clamped at {} never flows out, reach {}",
+                    position,
+                    reach
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_outflow_walls_delete_particles() {
+        let outflow = walls(WallBoundary::Outflow);
+        let solid = Boundaries::default();
+        let kernel = Kernel::Quadratic;
+        for (position, inside_wall) in [
+            (Vec2::new(8., 8.), false),
+            (Vec2::new(2.5, 8.), false),
+            (Vec2::new(1.9, 8.), true),
+            (Vec2::new(8., 14.), true),
+            (Vec2::new(8., 13.9), false),
+        ] {
+            let dx = 0.25;
+            assert_eq!(
+                outflow.flows_out(position * dx, 16, 16, dx, kernel),
+                inside_wall,
+                "outflow at {}",
+                position
+            );
+            assert!(!solid.flows_out(position * dx, 16, 16, dx, kernel));
+        }
+    }
+
+    #[test]
+    fn prediction_slows_particles_heading_for_solid_walls() {
+        let mut boundaries = Boundaries::default();
+        for (prediction_time, slowed) in [(DEFAULT_WALL_PREDICTION_TIME, true), (0., false)] {
+            boundaries.prediction_time = prediction_time;
+            let mut position = Vec2::new(4., 8.);
+            let mut velocity = Vec2::new(-1e3, 0.);
+            boundaries.confine(&mut position, &mut velocity, 16, 16, 1., Kernel::Quadratic);
+            assert_eq!(
+                velocity.x > -1e3,
+                slowed,
+                "velocity {} with prediction time {}",
+                velocity,
+                prediction_time
+            );
+        }
+    }
+}
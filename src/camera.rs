@@ -7,9 +7,10 @@ pub(super) fn setup_camera(mut commands: Commands, grid: Res<Grid>, wnds: Res<Wi
     let mut cb = Camera2dBundle::default();
 
     let wnd = wnds.get_primary().unwrap();
-    let size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+    let size = Vec2::new(wnd.width(), wnd.height());
 
-    let scale = f32::min(size.x, size.y) / grid.width as f32; // todo in response to events.
+    // fit the whole grid in the window
+    let scale = f32::min(size.x / grid.width as f32, size.y / grid.height as f32); // todo in response to events.
 
     cb.transform = Transform::from_translation(Vec3::new(
         size.x / (scale * 2.0),
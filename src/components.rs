@@ -1,21 +1,28 @@
+use std::ops::{Add, Mul, Sub};
+
+use bevy::ecs::system::EntityCommands;
 use bevy::math::{Mat2, Vec2};
 use bevy::prelude::*;
 
+use crate::defaults::{FLUID_CONTACT_FIELD, ICE_CONTACT_FIELD};
+use crate::kernel::MAX_STENCIL_SIZE;
+use crate::linalg::*;
+
 // Tags particle entities
 #[derive(Component)]
 pub(super) struct ParticleTag;
 
 // todo move rendering to GPU shader. over 70% of traced CPU time is inside sprite stuff.
 
-// XY position
+// XY position in meters
 #[derive(Component, Debug)]
 pub(super) struct Position(pub(super) Vec2);
 
-// XY velocity
+// XY velocity in meters per second
 #[derive(Component, Debug)]
 pub(super) struct Velocity(pub(super) Vec2);
 
-// mass
+// mass in kg
 #[derive(Component)]
 pub(super) struct Mass(pub(super) f32);
 
@@ -23,6 +30,11 @@ pub(super) struct Mass(pub(super) f32);
 #[derive(Component)]
 pub(super) struct AffineMomentum(pub(super) Mat2);
 
+// particle velocity gradient from the grid, drives the deformation update and viscous stresses.
+// equal to the affine momentum with MLS-MPM transfers.
+#[derive(Component)]
+pub(super) struct VelocityGradient(pub(super) Mat2);
+
 // fluid constitutive model properties
 #[derive(Clone, Copy, Component)]
 pub(super) struct NewtonianFluidModel {
@@ -30,36 +42,169 @@ pub(super) struct NewtonianFluidModel {
     pub(super) dynamic_viscosity: f32,
     pub(super) eos_stiffness: f32,
     pub(super) eos_power: f32,
+    // pulls the free surface inwards so small amounts of liquid ball up, 0 to disable
+    pub(super) surface_tension: f32,
+    // keeps this fluid apart from fluids with a different rest density, 0 to disable
+    pub(super) interfacial_tension: f32,
 }
 
 impl ConstitutiveModel for NewtonianFluidModel {
-    fn new_particle(
-        self,
-        commands: &mut Commands,
-        texture: Handle<Image>,
-        at: Vec2,
-        mass: f32,
-        created_at: usize,
-        vel: Option<Vec2>,
-        max_age: Option<usize>,
-    ) {
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture,
-                transform: Transform::from_scale(Vec3::splat(0.002)),
-                ..Default::default()
-            })
-            .insert_bundle((
-                Position(at),
-                self,
-                Mass(mass),
-                Velocity(vel.unwrap_or(Vec2::ZERO)),
-                MaxAge(max_age.unwrap_or(5000)),
-                AffineMomentum(Mat2::ZERO),
-                CreatedAt(created_at),
-                CellMassMomentumContributions([GridMassAndMomentumChange(0, 0., Vec2::ZERO); 9]),
-                ParticleTag,
-            ));
+    const SPRITE_SCALE: f32 = 0.002;
+
+    fn stress(&self, density: f32, affine_momentum: Mat2) -> Mat2 {
+        let pressure = eos_pressure(
+            density,
+            self.rest_density,
+            self.eos_stiffness,
+            self.eos_power,
+        );
+        let mut stress = Mat2::from_cols(Vec2::new(-pressure, 0.0), Vec2::new(0.0, -pressure));
+        let mut strain = affine_momentum;
+        let trace = strain.y_axis.x + strain.x_axis.y;
+        strain.y_axis.x = trace;
+        strain.x_axis.y = trace;
+        let viscosity_term = strain * self.dynamic_viscosity;
+        stress += viscosity_term;
+        stress
+    }
+
+    fn rest_density(&self) -> Option<f32> {
+        Some(self.rest_density)
+    }
+
+    fn surface_tension_stress(&self, density_gradient: Vec2) -> Mat2 {
+        // the color field is density relative to rest density
+        continuum_surface_stress(self.surface_tension, density_gradient / self.rest_density)
+    }
+
+    fn interfacial_tension(&self) -> f32 {
+        self.interfacial_tension
+    }
+
+    fn wave_speed(&self, _: f32) -> f32 {
+        // sound speed of the equation of state at rest
+        f32::sqrt(eos_bulk_modulus(self.eos_stiffness, self.eos_power) / self.rest_density)
+    }
+}
+
+// generalized newtonian fluid properties (Herschel-Bulkley), viscosity depends on the shear rate.
+// flow_index < 1 is shear thinning, > 1 is shear thickening, yield_stress > 0 is a Bingham-like plastic.
+#[derive(Clone, Copy, Component)]
+pub(super) struct NonNewtonianFluidModel {
+    pub(super) rest_density: f32,
+    pub(super) consistency: f32,
+    pub(super) flow_index: f32,
+    pub(super) yield_stress: f32,
+    // cap on the effective viscosity where the shear rate goes to zero
+    pub(super) max_viscosity: f32,
+    pub(super) eos_stiffness: f32,
+    pub(super) eos_power: f32,
+}
+
+impl NonNewtonianFluidModel {
+    pub(super) fn effective_viscosity(&self, shear_rate: f32) -> f32 {
+        if shear_rate < f32::EPSILON {
+            return self.max_viscosity;
+        }
+        let viscosity = self.consistency * shear_rate.powf(self.flow_index - 1.)
+            + self.yield_stress / shear_rate;
+        viscosity.min(self.max_viscosity)
+    }
+}
+
+impl ConstitutiveModel for NonNewtonianFluidModel {
+    const SPRITE_SCALE: f32 = 0.002;
+
+    fn stress(&self, density: f32, affine_momentum: Mat2) -> Mat2 {
+        let pressure = eos_pressure(
+            density,
+            self.rest_density,
+            self.eos_stiffness,
+            self.eos_power,
+        );
+        let mut stress = Mat2::from_cols(Vec2::new(-pressure, 0.0), Vec2::new(0.0, -pressure));
+
+        let strain_rate = strain_rate(affine_momentum);
+        let shear_rate = f32::sqrt(
+            2. * (strain_rate.x_axis.length_squared() + strain_rate.y_axis.length_squared()),
+        );
+        let viscosity_term = strain_rate * (2. * self.effective_viscosity(shear_rate));
+        stress += viscosity_term;
+        stress
+    }
+
+    fn rest_density(&self) -> Option<f32> {
+        Some(self.rest_density)
+    }
+
+    fn wave_speed(&self, _: f32) -> f32 {
+        f32::sqrt(eos_bulk_modulus(self.eos_stiffness, self.eos_power) / self.rest_density)
+    }
+}
+
+// viscoelastic fluid properties (Oldroyd-B), for slime and gels.
+// elastic stress builds up like a solid but relaxes back to rest over relaxation_time.
+#[derive(Clone, Copy, Component)]
+pub(super) struct ViscoelasticModel {
+    // elastic left cauchy-green tensor b = Fe Fe^T, identity when relaxed
+    pub(super) left_cauchy_green: Mat2,
+    pub(super) rest_density: f32,
+    // viscosity of the solvent around the polymer
+    pub(super) dynamic_viscosity: f32,
+    pub(super) elastic_mu: f32,
+    // time constant of the elastic stress decay, in seconds. 0 relaxes instantly
+    pub(super) relaxation_time: f32,
+    pub(super) eos_stiffness: f32,
+    pub(super) eos_power: f32,
+}
+
+impl ConstitutiveModel for ViscoelasticModel {
+    const SPRITE_SCALE: f32 = 0.002;
+    const TRACKS_DEFORMATION: bool = true;
+
+    fn stress(&self, density: f32, affine_momentum: Mat2) -> Mat2 {
+        let pressure = eos_pressure(
+            density,
+            self.rest_density,
+            self.eos_stiffness,
+            self.eos_power,
+        );
+        let mut stress = Mat2::from_cols(Vec2::new(-pressure, 0.0), Vec2::new(0.0, -pressure));
+
+        // solvent viscosity on the strain rate, plus the polymer stress mu(b - I)
+        stress += strain_rate(affine_momentum) * (2. * self.dynamic_viscosity);
+        stress += self
+            .left_cauchy_green
+            .sub(Mat2::IDENTITY)
+            .mul(self.elastic_mu);
+        stress
+    }
+
+    fn rest_density(&self) -> Option<f32> {
+        Some(self.rest_density)
+    }
+
+    fn wave_speed(&self, _: f32) -> f32 {
+        // sound speed plus the stiffening from the polymer
+        let bulk_modulus = eos_bulk_modulus(self.eos_stiffness, self.eos_power);
+        f32::sqrt((bulk_modulus + 2. * self.elastic_mu) / self.rest_density)
+    }
+
+    fn update_deformation(&mut self, affine_momentum: Mat2, dt: f32) {
+        // upper convected derivative, b = (I + dt C) b (I + dt C)^T
+        let step = Mat2::IDENTITY.add(affine_momentum.mul(dt));
+        let b = step
+            .mul_mat2(&self.left_cauchy_green)
+            .mul_mat2(&step.transpose());
+
+        // relax towards identity, implicit so it stays stable when dt > relaxation_time.
+        // no relaxation time relaxes instantly, leaving a purely viscous fluid
+        if self.relaxation_time <= 0. {
+            self.left_cauchy_green = Mat2::IDENTITY;
+            return;
+        }
+        let relax = dt / self.relaxation_time;
+        self.left_cauchy_green = b.add(Mat2::IDENTITY.mul(relax)).mul(1. / (1. + relax));
     }
 }
 
@@ -67,65 +212,848 @@ impl ConstitutiveModel for NewtonianFluidModel {
 #[derive(Clone, Copy, Component)]
 pub(super) struct NeoHookeanHyperElasticModel {
     pub(super) deformation_gradient: Mat2,
+    // lame parameters in pascals, see lame_parameters
     pub(super) elastic_lambda: f32,
-    // youngs modulus
     pub(super) elastic_mu: f32, // shear modulus
+    // permanent deformation once yielded, None is perfectly elastic
+    pub(super) plasticity: Option<VonMisesPlasticity>,
+    // tensile strain energy density in J/m^3 where damage starts, fully broken at twice this. None is unbreakable
+    pub(super) fracture_toughness: Option<f32>,
+}
+
+// von Mises yield criterion in log-strain space with linear isotropic hardening
+#[derive(Clone, Copy)]
+pub(super) struct VonMisesPlasticity {
+    pub(super) yield_stress: f32,
+    pub(super) hardening: f32,
+    // accumulated plastic strain, raises the yield stress by hardening * plastic_strain
+    pub(super) plastic_strain: f32,
 }
 
 impl ConstitutiveModel for NeoHookeanHyperElasticModel {
-    fn new_particle(
-        self,
-        commands: &mut Commands,
-        texture: Handle<Image>,
-        at: Vec2,
-        mass: f32,
-        created_at: usize,
-        vel: Option<Vec2>,
-        max_age: Option<usize>,
-    ) {
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture,
-                transform: Transform::from_scale(Vec3::splat(0.005)),
-                ..Default::default()
-            })
-            .insert_bundle((
-                Position(at),
-                self,
-                Mass(mass),
-                Velocity(vel.unwrap_or(Vec2::ZERO)),
-                MaxAge(max_age.unwrap_or(5000)),
-                AffineMomentum(Mat2::ZERO),
-                CreatedAt(created_at),
-                CellMassMomentumContributions([GridMassAndMomentumChange(0, 0., Vec2::ZERO); 9]),
-                ParticleTag,
-            ));
+    const SPRITE_SCALE: f32 = 0.005;
+    const TRACKS_DEFORMATION: bool = true;
+    const IMPLICIT: bool = true;
+
+    fn stress(&self, _: f32, _: Mat2) -> Mat2 {
+        let j: f32 = self.deformation_gradient.determinant();
+
+        let f_t: Mat2 = self.deformation_gradient.transpose();
+        let f_inv_t = f_t.inverse();
+        let f_minus_f_inv_t = self.deformation_gradient.sub(f_inv_t);
+
+        let p_term_0: Mat2 = f_minus_f_inv_t.mul(self.elastic_mu);
+        let p_term_1: Mat2 = f_inv_t.mul(j.ln() * self.elastic_lambda);
+        let p_combined: Mat2 = p_term_0.add(p_term_1);
+
+        p_combined.mul_mat2(&f_t)
+    }
+
+    fn wave_speed(&self, density: f32) -> f32 {
+        p_wave_speed(self.elastic_lambda, self.elastic_mu, density)
+    }
+
+    fn stress_differential(&self, deformation_step: Mat2) -> Mat2 {
+        // dP = mu dF + (mu - lambda ln J) F^-T dF^T F^-T + lambda tr(F^-1 dF) F^-T, with dF = deformation_step F
+        let f = self.deformation_gradient;
+        let j: f32 = f.determinant();
+        let trace = deformation_step.x_axis.x + deformation_step.y_axis.y;
+        deformation_step
+            .mul_mat2(&f)
+            .mul_mat2(&f.transpose())
+            .mul(self.elastic_mu)
+            .add(
+                deformation_step
+                    .transpose()
+                    .mul(self.elastic_mu - self.elastic_lambda * j.ln()),
+            )
+            .add(Mat2::IDENTITY.mul(self.elastic_lambda * trace))
+    }
+
+    fn update_deformation(&mut self, affine_momentum: Mat2, dt: f32) {
+        self.deformation_gradient = Mat2::IDENTITY
+            .add(affine_momentum.mul(dt))
+            .mul_mat2(&self.deformation_gradient);
+        self.return_map();
+    }
+
+    fn insert_extra_components(&self, particle: &mut EntityCommands) {
+        particle.insert(Damage(0.));
+    }
+}
+
+// solid constitutive model properties, fixed corotated hyperelasticity (Stomakhin et al. 2012).
+// stays well defined when elements invert, unlike the log(J) term of neo-hookean.
+#[derive(Clone, Copy, Component)]
+pub(super) struct FixedCorotatedModel {
+    pub(super) deformation_gradient: Mat2,
+    pub(super) elastic_lambda: f32,
+    pub(super) elastic_mu: f32,
+}
+
+impl ConstitutiveModel for FixedCorotatedModel {
+    const SPRITE_SCALE: f32 = 0.005;
+    const TRACKS_DEFORMATION: bool = true;
+    const IMPLICIT: bool = true;
+
+    fn stress(&self, _: f32, _: Mat2) -> Mat2 {
+        // 2mu(F - R)F^T + lambda(J - 1)J, no division by J so inverted elements are fine
+        let f = self.deformation_gradient;
+        let j: f32 = f.determinant();
+        let (r, _) = polar_decomposition(f);
+        f.sub(r)
+            .mul_mat2(&f.transpose())
+            .mul(2. * self.elastic_mu)
+            .add(Mat2::IDENTITY.mul(self.elastic_lambda * (j - 1.) * j))
+    }
+
+    fn wave_speed(&self, density: f32) -> f32 {
+        p_wave_speed(self.elastic_lambda, self.elastic_mu, density)
+    }
+
+    fn stress_differential(&self, deformation_step: Mat2) -> Mat2 {
+        fixed_corotated_stress_differential(
+            self.deformation_gradient,
+            self.elastic_lambda,
+            self.elastic_mu,
+            deformation_step,
+        )
+    }
+
+    fn update_deformation(&mut self, affine_momentum: Mat2, dt: f32) {
+        self.deformation_gradient = Mat2::IDENTITY
+            .add(affine_momentum.mul(dt))
+            .mul_mat2(&self.deformation_gradient);
+    }
+}
+
+// change of P F^T for fixed corotated elasticity when F changes by deformation_step * F.
+// P = 2mu(F - R) + lambda(J - 1) cof(F), where R rotates by atan2(f21 - f12, f11 + f22) in 2d.
+fn fixed_corotated_stress_differential(
+    f: Mat2,
+    elastic_lambda: f32,
+    elastic_mu: f32,
+    deformation_step: Mat2,
+) -> Mat2 {
+    let delta_f = deformation_step.mul_mat2(&f);
+    let j: f32 = f.determinant();
+    let delta_j = j * (deformation_step.x_axis.x + deformation_step.y_axis.y);
+
+    let x = f.x_axis.x + f.y_axis.y;
+    let y = f.x_axis.y - f.y_axis.x;
+    let norm_squared = x * x + y * y;
+    let delta_r = if norm_squared < f32::EPSILON {
+        Mat2::ZERO
+    } else {
+        let delta_x = delta_f.x_axis.x + delta_f.y_axis.y;
+        let delta_y = delta_f.x_axis.y - delta_f.y_axis.x;
+        let delta_angle = (x * delta_y - y * delta_x) / norm_squared;
+        let norm = norm_squared.sqrt();
+        let c = x / norm;
+        let s = y / norm;
+        Mat2::from_cols(Vec2::new(-s, c), Vec2::new(-c, -s)).mul(delta_angle)
+    };
+
+    delta_f
+        .sub(delta_r)
+        .mul(2. * elastic_mu)
+        .add(cofactor(f).mul(elastic_lambda * delta_j))
+        .add(cofactor(delta_f).mul(elastic_lambda * (j - 1.)))
+        .mul_mat2(&f.transpose())
+}
+
+// granular constitutive model properties (Drucker-Prager elastoplasticity, Klar et al. 2016)
+#[derive(Clone, Copy, Component)]
+pub(super) struct DruckerPragerPlasticModel {
+    // elastic part of the deformation gradient, plastic flow is projected out by return mapping
+    pub(super) deformation_gradient: Mat2,
+    pub(super) elastic_lambda: f32,
+    pub(super) elastic_mu: f32,
+    // friction angle in degrees before any hardening
+    pub(super) friction_angle: f32,
+    // tensile strength in log-strain units, 0 is cohesionless
+    pub(super) cohesion: f32,
+    // hardening parameters h1, h2, h3 in degrees, see section 7.3 of the paper
+    pub(super) hardening: Vec3,
+    // accumulated plastic deformation driving the hardening
+    pub(super) hardening_state: f32,
+}
+
+impl DruckerPragerPlasticModel {
+    // yield surface cone size from the current friction angle
+    pub(super) fn alpha(&self) -> f32 {
+        let friction_angle = self.friction_angle
+            + (self.hardening.x * self.hardening_state - self.hardening.z)
+                * f32::exp(-self.hardening.y * self.hardening_state);
+        let sin_phi = friction_angle.max(0.).to_radians().sin();
+        f32::sqrt(2. / 3.) * (2. * sin_phi) / (3. - sin_phi)
+    }
+}
+
+impl ConstitutiveModel for DruckerPragerPlasticModel {
+    const SPRITE_SCALE: f32 = 0.003;
+    const TRACKS_DEFORMATION: bool = true;
+
+    fn stress(&self, _: f32, _: Mat2) -> Mat2 {
+        // St. Venant-Kirchhoff with Hencky strain, evaluated in the principal frame.
+        let (u, sigma, _) = svd(self.deformation_gradient);
+        let log_sigma = Vec2::new(sigma.x.abs().max(1e-4).ln(), sigma.y.abs().max(1e-4).ln());
+        let log_sigma_trace = log_sigma.x + log_sigma.y;
+        let kirchhoff_principal =
+            log_sigma * (2. * self.elastic_mu) + Vec2::splat(self.elastic_lambda * log_sigma_trace);
+        u.mul_mat2(&Mat2::from_diagonal(kirchhoff_principal))
+            .mul_mat2(&u.transpose())
+    }
+
+    fn wave_speed(&self, density: f32) -> f32 {
+        p_wave_speed(self.elastic_lambda, self.elastic_mu, density)
+    }
+
+    fn update_deformation(&mut self, affine_momentum: Mat2, dt: f32) {
+        self.deformation_gradient = Mat2::IDENTITY
+            .add(affine_momentum.mul(dt))
+            .mul_mat2(&self.deformation_gradient);
+        self.return_map();
+    }
+}
+
+// snow constitutive model properties (Stomakhin et al. 2013, "A material point method for snow simulation")
+#[derive(Clone, Copy, Component)]
+pub(super) struct SnowModel {
+    // total deformation is elastic_deformation_gradient * plastic_deformation_gradient
+    pub(super) elastic_deformation_gradient: Mat2,
+    pub(super) plastic_deformation_gradient: Mat2,
+    pub(super) elastic_lambda: f32,
+    pub(super) elastic_mu: f32,
+    // singular values of the elastic part are clamped to [1 - critical_compression, 1 + critical_stretch]
+    pub(super) critical_compression: f32,
+    pub(super) critical_stretch: f32,
+    // lame parameters are scaled by exp(hardening * (1 - plastic jacobian))
+    pub(super) hardening: f32,
+}
+
+impl SnowModel {
+    // lame parameters hardened (or softened) by the plastic volume change
+    pub(super) fn hardened_lame_parameters(&self) -> (f32, f32) {
+        let plastic_j = self.plastic_deformation_gradient.determinant();
+        let scale = f32::exp(self.hardening * (1. - plastic_j));
+        (self.elastic_lambda * scale, self.elastic_mu * scale)
+    }
+}
+
+impl ConstitutiveModel for SnowModel {
+    const SPRITE_SCALE: f32 = 0.003;
+    const TRACKS_DEFORMATION: bool = true;
+    const IMPLICIT: bool = true;
+
+    fn stress(&self, _: f32, _: Mat2) -> Mat2 {
+        // fixed corotated elasticity on the elastic part, with hardened lame parameters
+        let f_e = self.elastic_deformation_gradient;
+        let j: f32 = f_e.determinant();
+        let (elastic_lambda, elastic_mu) = self.hardened_lame_parameters();
+        let (r, _) = polar_decomposition(f_e);
+        f_e.sub(r)
+            .mul_mat2(&f_e.transpose())
+            .mul(2. * elastic_mu)
+            .add(Mat2::IDENTITY.mul(elastic_lambda * (j - 1.) * j))
+    }
+
+    fn wave_speed(&self, density: f32) -> f32 {
+        let (elastic_lambda, elastic_mu) = self.hardened_lame_parameters();
+        p_wave_speed(elastic_lambda, elastic_mu, density)
+    }
+
+    fn stress_differential(&self, deformation_step: Mat2) -> Mat2 {
+        // plastic flow is held fixed over the step
+        let (elastic_lambda, elastic_mu) = self.hardened_lame_parameters();
+        fixed_corotated_stress_differential(
+            self.elastic_deformation_gradient,
+            elastic_lambda,
+            elastic_mu,
+            deformation_step,
+        )
+    }
+
+    fn update_deformation(&mut self, affine_momentum: Mat2, dt: f32) {
+        // the whole update is elastic until return mapping clamps it.
+        self.elastic_deformation_gradient = Mat2::IDENTITY
+            .add(affine_momentum.mul(dt))
+            .mul_mat2(&self.elastic_deformation_gradient);
+        self.return_map();
     }
 }
 
+// equation of state shared by the fluids, pressure rises with the density ratio to the power gamma.
+// expanded fluid is clamped to a tiny tension rather than pulling itself back together.
+pub(super) fn eos_pressure(density: f32, rest_density: f32, stiffness: f32, gamma: f32) -> f32 {
+    f32::max(
+        -0.1,
+        stiffness * (f32::powf(density / rest_density, gamma) - 1.0),
+    )
+}
+
+// bulk modulus of the equation of state at rest, the fluid's sound speed is sqrt(this / rest_density)
+pub(super) fn eos_bulk_modulus(stiffness: f32, gamma: f32) -> f32 {
+    stiffness * gamma
+}
+
+// continuum surface stress (Lafaurie et al. 1994), gamma * (|n| I - n n^T / |n|).
+// n is the gradient of a color field that goes from 0 outside to 1 inside the fluid.
+pub(super) fn continuum_surface_stress(coefficient: f32, n: Vec2) -> Mat2 {
+    let n_length = n.length();
+    if coefficient <= 0. || n_length < 1e-4 {
+        return Mat2::ZERO;
+    }
+    let n_outer_n = Mat2::from_cols(n * n.x, n * n.y);
+    (Mat2::IDENTITY * n_length - n_outer_n * (1. / n_length)) * coefficient
+}
+
+// strain rate is the symmetric part of the velocity gradient
+pub(super) fn strain_rate(velocity_gradient: Mat2) -> Mat2 {
+    velocity_gradient
+        .add(velocity_gradient.transpose())
+        .mul(0.5)
+}
+
+// material presets are in SI units: pascals, pascal seconds, newtons per meter and kg/m^3,
+// with the 2d simulation standing for a slab 1m deep.
+// densities, thermal properties and the sand and snow moduli are real values. the other stiffnesses,
+// viscosities and surface tensions are scaled, tuned to look right at cells a meter wide:
+// - steel, wood and ice are roughly 1000 to 3000 times softer than the real material, so explicit steps of DEFAULT_DT stay stable.
+// - fluids are far more compressible (water's bulk modulus is 2.2 GPa), for the same reason.
+// - viscosities and surface tensions are thousands of times larger, or they wouldn't show at this scale.
+// the solid and newtonian fluid presets note the real values they stand in for.
+
+// densities of the solid presets, fluids carry their own rest density
+pub(super) const STEEL_DENSITY: f32 = 7850.;
+pub(super) const WOOD_DENSITY: f32 = 600.;
+pub(super) const ICE_DENSITY: f32 = 917.;
+pub(super) const SAND_DENSITY: f32 = 1600.;
+pub(super) const SNOW_DENSITY: f32 = 400.;
+
+// lame parameters (lambda, mu) from young's modulus and poisson's ratio
+pub(super) fn lame_parameters(youngs_modulus: f32, poisson_ratio: f32) -> (f32, f32) {
+    let lambda =
+        youngs_modulus * poisson_ratio / ((1. + poisson_ratio) * (1. - 2. * poisson_ratio));
+    let mu = youngs_modulus / (2. * (1. + poisson_ratio));
+    (lambda, mu)
+}
+
+// speed of compression waves through an elastic solid
+pub(super) fn p_wave_speed(elastic_lambda: f32, elastic_mu: f32, density: f32) -> f32 {
+    f32::sqrt((elastic_lambda + 2. * elastic_mu) / density)
+}
+
+// scaled: 69 MPa young's modulus, real steel is around 200 GPa
 pub(super) fn steel_properties() -> NeoHookeanHyperElasticModel {
+    let (elastic_lambda, elastic_mu) = lame_parameters(69e6, 0.35);
     NeoHookeanHyperElasticModel {
         deformation_gradient: Default::default(),
-        elastic_lambda: 180. * 1000.,
-        elastic_mu: 78. * 1000.,
+        elastic_lambda,
+        elastic_mu,
+        plasticity: Some(VonMisesPlasticity {
+            yield_stress: 330e3,
+            hardening: 160e3,
+            plastic_strain: 0.,
+        }),
+        fracture_toughness: None,
     }
 }
 
+// scaled: 9.9 MPa young's modulus, plywood is around 9 GPa.
+// this is made stiffer relative to its density than steel so the tower stands up.
+pub(super) fn wood_properties() -> NeoHookeanHyperElasticModel {
+    let (elastic_lambda, elastic_mu) = lame_parameters(9.9e6, 0.375);
+    NeoHookeanHyperElasticModel {
+        deformation_gradient: Default::default(),
+        elastic_lambda,
+        elastic_mu,
+        plasticity: Some(VonMisesPlasticity {
+            yield_stress: 240e3,
+            hardening: 60e3,
+            plastic_strain: 0.,
+        }),
+        fracture_toughness: Some(9e3),
+    }
+}
+
+// scaled: 10 MPa young's modulus, real ice is around 9 GPa
+pub(super) fn ice_properties() -> NeoHookeanHyperElasticModel {
+    let (elastic_lambda, elastic_mu) = lame_parameters(10e6, 0.36);
+    NeoHookeanHyperElasticModel {
+        deformation_gradient: Default::default(),
+        elastic_lambda,
+        elastic_mu,
+        plasticity: None,
+        fracture_toughness: None,
+    }
+}
+
+// same scaled modulus as steel_properties
+pub(super) fn corotated_steel_properties() -> FixedCorotatedModel {
+    let (elastic_lambda, elastic_mu) = lame_parameters(69e6, 0.35);
+    FixedCorotatedModel {
+        deformation_gradient: Default::default(),
+        elastic_lambda,
+        elastic_mu,
+    }
+}
+
+// 48 MPa young's modulus, within the 10 to 100 MPa of a real sand pack
+pub(super) fn sand_properties() -> DruckerPragerPlasticModel {
+    let (elastic_lambda, elastic_mu) = lame_parameters(48e6, 0.3);
+    DruckerPragerPlasticModel {
+        deformation_gradient: Default::default(),
+        elastic_lambda,
+        elastic_mu,
+        friction_angle: 35.,
+        cohesion: 0.,
+        hardening: Vec3::new(9., 0.2, 10.),
+        hardening_state: 0.,
+    }
+}
+
+// 24 MPa young's modulus, within the 1 to 100 MPa of real snow
+pub(super) fn snow_properties() -> SnowModel {
+    let (elastic_lambda, elastic_mu) = lame_parameters(24e6, 0.3);
+    SnowModel {
+        elastic_deformation_gradient: Default::default(),
+        plastic_deformation_gradient: Default::default(),
+        elastic_lambda,
+        elastic_mu,
+        critical_compression: 2.5e-2,
+        critical_stretch: 7.5e-3,
+        hardening: 10.,
+    }
+}
+
+// scaled: real water has 0.001 Pa s viscosity and 0.072 N/m surface tension
 pub(super) fn water_properties() -> NewtonianFluidModel {
     NewtonianFluidModel {
-        rest_density: 4.,
-        dynamic_viscosity: 0.1,
-        eos_stiffness: 100.,
+        rest_density: 1000.,
+        dynamic_viscosity: 25.,
+        eos_stiffness: 25e3,
+        eos_power: 4.,
+        surface_tension: 250.,
+        interfacial_tension: 500.,
+    }
+}
+
+// lighter than water, floats on top. scaled like water, real oil is around 0.05 Pa s
+pub(super) fn oil_properties() -> NewtonianFluidModel {
+    NewtonianFluidModel {
+        rest_density: 800.,
+        dynamic_viscosity: 125.,
+        eos_stiffness: 25e3,
+        eos_power: 4.,
+        surface_tension: 125.,
+        interfacial_tension: 500.,
+    }
+}
+
+// heavier and much more viscous than water. scaled, real honey is around 10 Pa s
+pub(super) fn honey_properties() -> NewtonianFluidModel {
+    NewtonianFluidModel {
+        rest_density: 1400.,
+        dynamic_viscosity: 5000.,
+        eos_stiffness: 25e3,
+        eos_power: 4.,
+        surface_tension: 250.,
+        interfacial_tension: 0.,
+    }
+}
+
+// sinks through everything, stiffer equation of state to hold up its own weight.
+// scaled, real mercury has 0.0015 Pa s viscosity and 0.49 N/m surface tension
+pub(super) fn mercury_properties() -> NewtonianFluidModel {
+    NewtonianFluidModel {
+        rest_density: 13500.,
+        dynamic_viscosity: 25.,
+        eos_stiffness: 250e3,
+        eos_power: 4.,
+        surface_tension: 1000.,
+        interfacial_tension: 1000.,
+    }
+}
+
+// bingham plastic, sits in a heap until pushed hard enough
+pub(super) fn mud_properties() -> NonNewtonianFluidModel {
+    NonNewtonianFluidModel {
+        rest_density: 1000.,
+        consistency: 125.,
+        flow_index: 1.,
+        yield_stress: 1250.,
+        max_viscosity: 12.5e3,
+        eos_stiffness: 25e3,
+        eos_power: 4.,
+    }
+}
+
+// shear thinning with a yield stress
+pub(super) fn toothpaste_properties() -> NonNewtonianFluidModel {
+    NonNewtonianFluidModel {
+        rest_density: 1000.,
+        consistency: 1000.,
+        flow_index: 0.4,
+        yield_stress: 3750.,
+        max_viscosity: 12.5e3,
+        eos_stiffness: 25e3,
+        eos_power: 4.,
+    }
+}
+
+// shear thickening cornstarch and water
+pub(super) fn oobleck_properties() -> NonNewtonianFluidModel {
+    NonNewtonianFluidModel {
+        rest_density: 1000.,
+        consistency: 12.5,
+        flow_index: 1.8,
+        yield_stress: 0.,
+        max_viscosity: 12.5e3,
+        eos_stiffness: 25e3,
         eos_power: 4.,
     }
 }
 
-// computed changes to-be-applied to grid on next steps
+// stretchy, springs back a little before slowly flowing
+pub(super) fn slime_properties() -> ViscoelasticModel {
+    ViscoelasticModel {
+        left_cauchy_green: Mat2::IDENTITY,
+        rest_density: 1000.,
+        dynamic_viscosity: 125.,
+        elastic_mu: 5000.,
+        relaxation_time: 1.,
+        eos_stiffness: 25e3,
+        eos_power: 4.,
+    }
+}
+
+// solid damage from 0 (intact) to 1 (broken), degrades stiffness
+#[derive(Component)]
+pub(super) struct Damage(pub(super) f32);
+
+impl Damage {
+    // damaged material loses stiffness quadratically.
+    // fully damaged material has separated, so it only keeps the compressive part of its stress.
+    pub(super) fn degrade(&self, stress: Mat2) -> Mat2 {
+        if self.0 < 1. {
+            return stress * f32::powi(1. - self.0, 2);
+        }
+        let (principal_stress, directions) = symmetric_eigen(stress);
+        directions
+            .mul_mat2(&Mat2::from_diagonal(principal_stress.min(Vec2::ZERO)))
+            .mul_mat2(&directions.transpose())
+    }
+}
+
+// particle temperature in kelvin
 #[derive(Component)]
-pub(super) struct CellMassMomentumContributions(pub(super) [GridMassAndMomentumChange; 9]);
+pub(super) struct Temperature(pub(super) f32);
+
+// how a material conducts and stores heat
+#[derive(Clone, Copy, Component)]
+pub(super) struct ThermalProperties {
+    // W/(m K)
+    pub(super) conductivity: f32,
+    // specific heat capacity, J/(kg K)
+    pub(super) heat_capacity: f32,
+}
+
+pub(super) fn water_thermal_properties() -> ThermalProperties {
+    ThermalProperties {
+        conductivity: 0.6,
+        heat_capacity: 4200.,
+    }
+}
 
+pub(super) fn steel_thermal_properties() -> ThermalProperties {
+    ThermalProperties {
+        conductivity: 50.,
+        heat_capacity: 500.,
+    }
+}
+
+pub(super) fn wood_thermal_properties() -> ThermalProperties {
+    ThermalProperties {
+        conductivity: 0.15,
+        heat_capacity: 1700.,
+    }
+}
+
+pub(super) fn sand_thermal_properties() -> ThermalProperties {
+    ThermalProperties {
+        conductivity: 0.3,
+        heat_capacity: 800.,
+    }
+}
+
+pub(super) fn snow_thermal_properties() -> ThermalProperties {
+    ThermalProperties {
+        conductivity: 0.1,
+        heat_capacity: 2100.,
+    }
+}
+
+pub(super) fn ice_thermal_properties() -> ThermalProperties {
+    ThermalProperties {
+        conductivity: 2.2,
+        heat_capacity: 2100.,
+    }
+}
+
+// lets a particle melt from a neo-hookean solid into a newtonian fluid and freeze back.
+// heat crossing the melting point is first stored as latent heat, the phase flips once it is all absorbed or released.
+// the material, thermal properties and texture of the particle are swapped together.
+#[derive(Clone, Component)]
+pub(super) struct PhaseChange {
+    pub(super) melting_point: f32,
+    // J/kg
+    pub(super) latent_heat: f32,
+    // material used while frozen. the deformation gradient restarts from this on every freeze.
+    pub(super) solid: NeoHookeanHyperElasticModel,
+    pub(super) fluid: NewtonianFluidModel,
+    pub(super) solid_thermal_properties: ThermalProperties,
+    pub(super) fluid_thermal_properties: ThermalProperties,
+    pub(super) solid_texture: Handle<Image>,
+    pub(super) fluid_texture: Handle<Image>,
+    pub(super) solid_contact_field: usize,
+    pub(super) fluid_contact_field: usize,
+    // latent heat taken in so far, 0 is fully solid and latent_heat * mass is fully melted
+    pub(super) absorbed_heat: f32,
+}
+
+pub(super) fn ice_phase_change(asset_server: &AssetServer) -> PhaseChange {
+    PhaseChange {
+        melting_point: 273.,
+        latent_heat: 334e3,
+        solid: ice_properties(),
+        fluid: water_properties(),
+        solid_thermal_properties: ice_thermal_properties(),
+        fluid_thermal_properties: water_thermal_properties(),
+        solid_texture: asset_server.load("ice_particle.png"),
+        fluid_texture: asset_server.load("liquid_particle.png"),
+        solid_contact_field: ICE_CONTACT_FIELD,
+        fluid_contact_field: FLUID_CONTACT_FIELD,
+        absorbed_heat: 0.,
+    }
+}
+
+// rectangular region holding the material inside it at a fixed temperature
+#[derive(Clone, Copy, Component)]
+pub(super) struct HeatSource {
+    pub(super) min: Vec2,
+    pub(super) max: Vec2,
+    pub(super) temperature: f32,
+}
+
+// rigid box coupled two-way with the grid.
+// it takes momentum from grid cells moving into it and gives them its own velocity in Grid::update.
+#[derive(Clone, Copy, Component)]
+pub(super) struct RigidBody {
+    pub(super) position: Vec2,
+    // radians counter-clockwise
+    pub(super) rotation: f32,
+    pub(super) velocity: Vec2,
+    pub(super) angular_velocity: f32,
+    pub(super) half_extents: Vec2,
+    pub(super) mass: f32,
+    pub(super) inertia: f32,
+    // pinned bodies only rotate about their position, like a seesaw
+    pub(super) pinned: bool,
+    // momentum taken from the grid this step
+    pub(super) impulse: Vec2,
+    pub(super) angular_impulse: f32,
+}
+
+impl RigidBody {
+    // solid box, density in kg/m^3 over a depth of 1m like the particles
+    pub(super) fn new_box(position: Vec2, half_extents: Vec2, density: f32, pinned: bool) -> Self {
+        let mass = density * 4. * half_extents.x * half_extents.y;
+        RigidBody {
+            position,
+            rotation: 0.,
+            velocity: Vec2::ZERO,
+            angular_velocity: 0.,
+            half_extents,
+            mass,
+            inertia: mass * half_extents.length_squared() / 3.,
+            pinned,
+            impulse: Vec2::ZERO,
+            angular_impulse: 0.,
+        }
+    }
+
+    // outward normal of the closest face, if the point is inside or within margin of the body
+    pub(super) fn surface_normal(&self, point: Vec2, margin: f32) -> Option<Vec2> {
+        let rotation = Mat2::from_angle(self.rotation);
+        let local = rotation.transpose() * (point - self.position);
+        let outside = local.abs() - self.half_extents;
+        if outside.x > margin || outside.y > margin {
+            return None;
+        }
+        let local_normal = if outside.x > outside.y {
+            Vec2::new(local.x.signum(), 0.)
+        } else {
+            Vec2::new(0., local.y.signum())
+        };
+        Some(rotation * local_normal)
+    }
+
+    pub(super) fn velocity_at(&self, point: Vec2) -> Vec2 {
+        self.velocity + (point - self.position).perp() * self.angular_velocity
+    }
+
+    pub(super) fn add_impulse(&mut self, point: Vec2, impulse: Vec2) {
+        self.impulse += impulse;
+        self.angular_impulse += (point - self.position).perp_dot(impulse);
+    }
+}
+
+// light enough to float high in water
+pub(super) fn crate_body(position: Vec2) -> RigidBody {
+    RigidBody::new_box(position, Vec2::new(6., 6.), 500., false)
+}
+
+// long plank pinned at its middle
+pub(super) fn seesaw_body(position: Vec2) -> RigidBody {
+    RigidBody::new_box(position, Vec2::new(30., 1.5), 1500., true)
+}
+
+// static obstacle, the grid velocity is constrained within a cell of its surface
+#[derive(Clone, Component)]
+pub(super) struct Collider {
+    pub(super) shape: ColliderShape,
+    pub(super) boundary: BoundaryCondition,
+    // coulomb friction for separating boundaries
+    pub(super) friction: f32,
+}
+
+// moves a collider along a looping keyframed path and/or spins it, see kinematic.rs.
+// the collider shape is defined at its rest position, offset and rotated about pivot by the current motion.
+#[derive(Clone, Component)]
+pub(super) struct KinematicMotion {
+    pub(super) keyframes: Vec<Keyframe>,
+    // constant angular velocity in radians per second, on top of the path rotation
+    pub(super) spin: f32,
+    pub(super) pivot: Vec2,
+    pub(super) time: f32,
+    // current state, its velocity is imposed on the grid
+    pub(super) offset: Vec2,
+    pub(super) rotation: f32,
+    pub(super) velocity: Vec2,
+    pub(super) angular_velocity: f32,
+}
+
+// offset and rotation at a time in seconds, linearly interpolated between keyframes
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Keyframe {
+    pub(super) time: f32,
+    pub(super) offset: Vec2,
+    pub(super) rotation: f32,
+}
+
+// shapes described by signed distance functions, negative inside. see sdf.rs
+#[derive(Clone)]
+pub(super) enum ColliderShape {
+    Circle {
+        center: Vec2,
+        radius: f32,
+    },
+    Box {
+        center: Vec2,
+        half_extents: Vec2,
+        rotation: f32,
+    },
+    Capsule {
+        a: Vec2,
+        b: Vec2,
+        radius: f32,
+    },
+    // vertices in order, either winding
+    Polygon(Vec<Vec2>),
+    Union(Box<ColliderShape>, Box<ColliderShape>),
+    // first shape with the second cut out of it
+    Difference(Box<ColliderShape>, Box<ColliderShape>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum BoundaryCondition {
+    // material touching the collider stops
+    Sticky,
+    // material slides along the surface but can't leave it
+    Slip,
+    // material can't move into the surface but is free to move away
+    Separate,
+}
+
+// computed heat changes to-be-applied to grid on next steps, the first .1 are used
+#[derive(Component)]
+pub(super) struct CellHeatContributions(
+    pub(super) [GridHeatChange; MAX_STENCIL_SIZE],
+    pub(super) usize,
+);
+
+impl CellHeatContributions {
+    pub(super) fn changes(&self) -> &[GridHeatChange] {
+        &self.0[..self.1]
+    }
+}
+
+// cell index, thermal mass, thermal mass * temperature, thermal mass * conductivity
 #[derive(Clone, Copy)]
-pub(super) struct GridMassAndMomentumChange(pub(super) usize, pub(super) f32, pub(super) Vec2);
+pub(super) struct GridHeatChange(
+    pub(super) usize,
+    pub(super) f32,
+    pub(super) f32,
+    pub(super) f32,
+);
+
+// fills the contribution slots before the first scatter
+pub(super) const NO_HEAT_CHANGE: GridHeatChange = GridHeatChange(0, 0., 0., 0.);
+
+// computed changes to-be-applied to grid on next steps, the first .1 are used
+#[derive(Component)]
+pub(super) struct CellMassMomentumContributions(
+    pub(super) [GridMassAndMomentumChange; MAX_STENCIL_SIZE],
+    pub(super) usize,
+);
+
+impl CellMassMomentumContributions {
+    pub(super) fn changes(&self) -> &[GridMassAndMomentumChange] {
+        &self.0[..self.1]
+    }
+}
+
+// cell index, mass, momentum, fluid rest volume, fluid interfacial tension
+#[derive(Clone, Copy)]
+pub(super) struct GridMassAndMomentumChange(
+    pub(super) usize,
+    pub(super) f32,
+    pub(super) Vec2,
+    pub(super) f32,
+    pub(super) f32,
+);
+
+pub(super) const NO_MASS_AND_MOMENTUM_CHANGE: GridMassAndMomentumChange =
+    GridMassAndMomentumChange(0, 0., Vec2::ZERO, 0., 0.);
+
+// grid velocity field used with multi-field contact, materials in different fields slide against each other.
+// set per spawner, see the *_CONTACT_FIELD defaults
+#[derive(Clone, Copy, Component)]
+pub(super) struct ContactField(pub(super) usize);
+
+// volume a fluid particle fills at its rest density
+#[derive(Component)]
+pub(super) struct RestVolume(pub(super) f32);
+
+// interfacial tension of a fluid particle, the grid keeps the smallest one in each cell
+#[derive(Component)]
+pub(super) struct InterfacialTension(pub(super) f32);
+
+// volume of a particle when it was spawned, in m^3 over the 1m depth.
+// solid stresses are measured from this undeformed volume.
+#[derive(Component)]
+pub(super) struct InitialVolume(pub(super) f32);
 
 // tick the entity was created on
 #[derive(Component)]
@@ -135,15 +1063,136 @@ pub(super) struct CreatedAt(pub(super) usize);
 #[derive(Component)]
 pub(super) struct MaxAge(pub(super) usize);
 
-pub trait ConstitutiveModel {
+// a material. implementing this and adding a ConstitutiveModelPlugin for it is all a new material needs.
+pub trait ConstitutiveModel: Component + Copy {
+    const SPRITE_SCALE: f32;
+    // whether update_deformation needs to run each step. fluids have no elastic memory.
+    const TRACKS_DEFORMATION: bool = false;
+    // hyperelastic solids with a stress_differential, which the implicit grid update can integrate
+    const IMPLICIT: bool = false;
+
+    // stress scattered onto the grid in P2G, where it is multiplied by the particle volume.
+    // solids return the kirchhoff stress, i.e. cauchy stress already scaled by the volume change J,
+    // so it is multiplied by their initial volume.
+    fn stress(&self, density: f32, affine_momentum: Mat2) -> Mat2;
+
+    // extra stress at the free surface, from the gradient of grid density around the particle.
+    fn surface_tension_stress(&self, _density_gradient: Vec2) -> Mat2 {
+        Mat2::ZERO
+    }
+
+    // fluids have a rest density. their particles get a RestVolume and an InterfacialTension,
+    // which mark out each fluid on the grid so the interface between two fluids can be found.
+    fn rest_density(&self) -> Option<f32> {
+        None
+    }
+
+    // coefficient of the surface stress where this fluid meets a fluid of another rest density.
+    // both fluids at an interface use the smaller of their two coefficients, so they pull on each other equally.
+    fn interfacial_tension(&self) -> f32 {
+        0.
+    }
+
+    // speed of the fastest stress wave through the material at this density, limits the stable time step
+    fn wave_speed(&self, _density: f32) -> f32 {
+        0.
+    }
+
+    // linearised change of the first piola-kirchhoff stress times F^T when F changes by deformation_step * F,
+    // with F^T held at the start of the step: dP F^T. this is the energy hessian the implicit solve needs.
+    fn stress_differential(&self, _deformation_step: Mat2) -> Mat2 {
+        Mat2::ZERO
+    }
+
+    // integrate the deformation gradient with the particle velocity gradient, and apply any plasticity.
+    fn update_deformation(&mut self, _affine_momentum: Mat2, _dt: f32) {}
+
+    // components only some materials need
+    fn insert_extra_components(&self, _particle: &mut EntityCommands) {}
+
+    #[allow(clippy::too_many_arguments)]
     fn new_particle(
         self,
         commands: &mut Commands,
         texture: Handle<Image>,
         at: Vec2,
         mass: f32,
+        volume: f32,
         created_at: usize,
         vel: Option<Vec2>,
         max_age: Option<usize>,
-    );
+    ) -> Entity {
+        let mut particle = commands.spawn_bundle(SpriteBundle {
+            texture,
+            transform: Transform::from_scale(Vec3::splat(Self::SPRITE_SCALE)),
+            ..Default::default()
+        });
+        particle.insert_bundle((
+            Position(at),
+            self,
+            Mass(mass),
+            InitialVolume(volume),
+            Velocity(vel.unwrap_or(Vec2::ZERO)),
+            MaxAge(max_age.unwrap_or(5000)),
+            AffineMomentum(Mat2::ZERO),
+            VelocityGradient(Mat2::ZERO),
+            CreatedAt(created_at),
+            CellMassMomentumContributions([NO_MASS_AND_MOMENTUM_CHANGE; MAX_STENCIL_SIZE], 0),
+            ParticleTag,
+        ));
+        if let Some(rest_density) = self.rest_density() {
+            particle.insert_bundle((
+                RestVolume(mass / rest_density),
+                InterfacialTension(self.interfacial_tension()),
+            ));
+        }
+        self.insert_extra_components(&mut particle);
+        particle.id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // shearing flow, x velocity grows with y
+    const SHEAR: Mat2 = Mat2::from_cols(Vec2::ZERO, Vec2::new(2., 0.));
+
+    #[test]
+    fn viscoelastic_without_relaxation_time_relaxes_instantly() {
+        for relaxation_time in [0., -1.] {
+            let mut slime = ViscoelasticModel {
+                relaxation_time,
+                ..slime_properties()
+            };
+            slime.update_deformation(SHEAR, 0.01);
+            assert_eq!(slime.left_cauchy_green, Mat2::IDENTITY);
+
+            // only the pressure and solvent viscosity are left
+            let pressure = eos_pressure(
+                4.2,
+                slime.rest_density,
+                slime.eos_stiffness,
+                slime.eos_power,
+            );
+            let viscous = Mat2::IDENTITY.mul(-pressure)
+                + strain_rate(SHEAR).mul(2. * slime.dynamic_viscosity);
+            assert_eq!(slime.stress(4.2, SHEAR), viscous);
+        }
+    }
+
+    #[test]
+    fn viscoelastic_stress_relaxes_once_the_flow_stops() {
+        let mut slime = slime_properties();
+        slime.update_deformation(SHEAR, 0.01);
+        let stretched = slime.left_cauchy_green.sub(Mat2::IDENTITY).y_axis.x;
+        assert!(stretched > 0.);
+
+        // after a few relaxation times the polymer is almost back at rest
+        for _ in 0..500 {
+            slime.update_deformation(Mat2::ZERO, 0.01);
+        }
+        let relaxed = slime.left_cauchy_green.sub(Mat2::IDENTITY).y_axis.x;
+        assert!(relaxed > 0. && relaxed < 0.01 * stretched);
+    }
 }
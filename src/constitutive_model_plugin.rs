@@ -0,0 +1,59 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::spawners::*;
+use crate::step_adaptive_dt::*;
+use crate::step_p2g::*;
+use crate::step_update_deformations::*;
+use crate::step_update_grid::gather_implicit_particles;
+use crate::substeps::SIMULATION_STAGE;
+
+// registers the spawner, time step, P2G, deformation update and implicit solve systems for particles made of material M
+pub(super) struct ConstitutiveModelPlugin<M>(PhantomData<M>);
+
+impl<M> Default for ConstitutiveModelPlugin<M> {
+    fn default() -> Self {
+        ConstitutiveModelPlugin(PhantomData)
+    }
+}
+
+impl<M: ConstitutiveModel> Plugin for ConstitutiveModelPlugin<M> {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(
+            SIMULATION_STAGE,
+            tick_spawners::<M>
+                .label("tick_spawners")
+                .before("reset_grid"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            measure_signal_speed::<M>
+                .label("measure_signal_speed")
+                .after("g2p"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            particles_to_grid::<M>.label("p2g").before("update_grid"),
+        );
+
+        if M::TRACKS_DEFORMATION {
+            app.add_system_to_stage(
+                SIMULATION_STAGE,
+                update_deformation_gradients::<M>
+                    .label("update_deformation_gradients")
+                    .before("update_damage"),
+            );
+        }
+
+        if M::IMPLICIT {
+            app.add_system_to_stage(
+                SIMULATION_STAGE,
+                gather_implicit_particles::<M>
+                    .label("gather_implicit_particles")
+                    .before("update_grid"),
+            );
+        }
+    }
+}
@@ -2,13 +2,46 @@
 //pub(super) const DEFAULT_WINDOW_HEIGHT: f32 = 1000.;
 // todo proper scaling.
 
-pub(super) const DEFAULT_GRID_WIDTH: usize = usize::pow(2, 8);
-
-// todo use me when spawning...? maybe variable for density.
-// pub(super) const PARTICLES_ACROSS_GRID: usize = 1024;
-// pub(super) const PARTICLES_ACROSS_CELL: usize = PARTICLES_ACROSS_GRID / DEFAULT_GRID_WIDTH;
+// simulation domain in meters and the size of a grid cell, 256 x 256 cells by default
+pub(super) const DEFAULT_DOMAIN_WIDTH: f32 = 256.;
+pub(super) const DEFAULT_DOMAIN_HEIGHT: f32 = 256.;
+pub(super) const DEFAULT_DX: f32 = 1.;
 
 pub(super) const DEFAULT_DT: f32 = 0.002;
+// about one step of DEFAULT_DT per frame at 60fps
+pub(super) const DEFAULT_SIMULATION_SPEED: f32 = 0.12;
+pub(super) const DEFAULT_MAX_SUBSTEPS: usize = 8;
+// adaptive dt range and courant number
+pub(super) const DEFAULT_MIN_DT: f32 = 0.0001;
+pub(super) const DEFAULT_MAX_DT: f32 = 0.01;
+pub(super) const DEFAULT_CFL: f32 = 0.4;
+// conjugate gradient limits for the implicit solve, tolerance is relative to the initial residual
+pub(super) const IMPLICIT_MAX_ITERATIONS: usize = 40;
+pub(super) const IMPLICIT_TOLERANCE: f32 = 1e-4;
+pub(super) const DEFAULT_FLIP_RATIO: f32 = 0.95;
 pub(super) const DEFAULT_GRAVITY: f32 = -9.8;
+pub(super) const DEFAULT_TEMPERATURE: f32 = 293.;
+// fraction of the temperature difference a cell can take from each of its four neighbours in a step.
+// explicit conduction oscillates and blows up above 0.25
+pub(super) const MAX_CONDUCTION_RATE: f32 = 0.2;
+pub(super) const DEFAULT_CONTACT_FRICTION: f32 = 0.3;
+
+// real conductivities take hours to move heat across cells a meter wide, conduction runs this much faster.
+// the thermal presets stay real values, only the conduction rate in Grid::conduct_heat is scaled.
+pub(super) const HEAT_CONDUCTION_SPEEDUP: f32 = 1e6;
+
+// cells covered by each wall, and how far ahead particles look for walls in seconds
+pub(super) const DEFAULT_WALL_THICKNESS: usize = 2;
+pub(super) const DEFAULT_WALL_PREDICTION_TIME: f32 = 0.1;
+
+// number of grid velocity fields for multi-field contact, and the field of each material in the scene.
+// fluids all share a field so they mix
+pub(super) const CONTACT_FIELDS: usize = 6;
+pub(super) const FLUID_CONTACT_FIELD: usize = 0;
+pub(super) const STEEL_CONTACT_FIELD: usize = 1;
+pub(super) const WOOD_CONTACT_FIELD: usize = 2;
+pub(super) const SAND_CONTACT_FIELD: usize = 3;
+pub(super) const SNOW_CONTACT_FIELD: usize = 4;
+pub(super) const ICE_CONTACT_FIELD: usize = 5;
 
 pub(super) const PAR_BATCH_SIZE: usize = usize::pow(2, 12);
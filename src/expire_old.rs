@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 
 use crate::components::*;
+use crate::grid::Grid;
 use crate::world::*;
 
 pub(super) fn delete_old_entities(
@@ -14,3 +15,18 @@ pub(super) fn delete_old_entities(
         }
     });
 }
+
+pub(super) fn delete_outflow_particles(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    particles: Query<(Entity, &Position), With<ParticleTag>>,
+) {
+    particles.for_each(|(id, position)| {
+        if grid
+            .boundaries
+            .flows_out(position.0, grid.width, grid.height, grid.dx, grid.kernel)
+        {
+            commands.entity(id).despawn();
+        }
+    });
+}
@@ -1,104 +1,374 @@
 use bevy::math::{Mat2, Vec2};
 use bevy::prelude::ResMut;
+use std::ops::Range;
+
+use crate::boundaries::*;
+use crate::components::{Collider, HeatSource, KinematicMotion, RigidBody};
+use crate::defaults::{CONTACT_FIELDS, HEAT_CONDUCTION_SPEEDUP, MAX_CONDUCTION_RATE};
+use crate::implicit::ImplicitParticle;
+use crate::kernel::Kernel;
 
 #[derive(Debug, Clone, Copy)]
 pub(super) struct Cell {
     pub(super) velocity: Vec2,
     pub(super) mass: f32,
+    // velocity from the transferred momentum alone, before forces and constraints. FLIP transfers the change
+    pub(super) previous_velocity: Vec2,
+    pub(super) previous_field_velocity: [Vec2; CONTACT_FIELDS],
+    // mass and rest volume of just the fluid particles, their ratio is the mean rest density of fluid in the cell
+    pub(super) fluid_mass: f32,
+    pub(super) fluid_volume: f32,
+    // smallest interfacial tension of the fluids in the cell, infinite without fluid
+    pub(super) fluid_interfacial_tension: f32,
+    // mass and momentum (then velocity) of each contact field, velocity and mass above are their sums
+    pub(super) field_mass: [f32; CONTACT_FIELDS],
+    pub(super) field_velocity: [Vec2; CONTACT_FIELDS],
+    // heat capacity of the mass in this cell
+    pub(super) thermal_mass: f32,
+    // summed as thermal_mass * temperature during P2G, then converted to temperature
+    pub(super) temperature: f32,
+    // summed as thermal_mass * conductivity during P2G, then converted to conductivity
+    pub(super) conductivity: f32,
+    // temperature change from conduction this step, interpolated back onto particles
+    pub(super) temperature_change: f32,
 }
 
-// MPM grid resource
+// MPM grid resource.
+// particles, colliders and rigid bodies live in meters, cell (x, y) covers [x, x + 1) * dx by [y, y + 1) * dx.
 #[derive(Clone)]
 pub(super) struct Grid {
     pub(super) cells: Vec<Cell>,
     pub(super) width: usize,
+    pub(super) height: usize,
+    // cell size in meters
+    pub(super) dx: f32,
+    // interpolation between particles and cells
+    pub(super) kernel: Kernel,
+    pub(super) boundaries: Boundaries,
 }
 
 impl Grid {
-    pub(super) fn new(width: usize) -> Grid {
+    // enough cells of size dx to cover a domain of the given size in meters
+    pub(super) fn new(domain_size: Vec2, dx: f32) -> Grid {
+        let width = (domain_size.x / dx).round() as usize;
+        let height = (domain_size.y / dx).round() as usize;
         Grid {
             cells: vec![
                 Cell {
                     velocity: Vec2::ZERO,
                     mass: 0.0,
+                    previous_velocity: Vec2::ZERO,
+                    previous_field_velocity: [Vec2::ZERO; CONTACT_FIELDS],
+                    fluid_mass: 0.0,
+                    fluid_volume: 0.0,
+                    fluid_interfacial_tension: f32::INFINITY,
+                    field_mass: [0.0; CONTACT_FIELDS],
+                    field_velocity: [Vec2::ZERO; CONTACT_FIELDS],
+                    thermal_mass: 0.0,
+                    temperature: 0.0,
+                    conductivity: 0.0,
+                    temperature_change: 0.0,
                 };
-                width * width
+                width * height
             ],
             width,
+            height,
+            dx,
+            kernel: Kernel::Quadratic,
+            boundaries: Boundaries::default(),
         }
     }
 
     pub(super) fn index_at(&self, x: usize, y: usize) -> usize {
-        x * self.width + y
+        x * self.height + y
+    }
+
+    // size in cells
+    pub(super) fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    // size in meters
+    pub(super) fn domain_size(&self) -> Vec2 {
+        self.size() * self.dx
+    }
+
+    pub(super) fn inv_dx(&self) -> f32 {
+        1.0 / self.dx
+    }
+
+    // center of a cell in meters
+    pub(super) fn node_position(&self, x: i32, y: i32) -> Vec2 {
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * self.dx
+    }
+
+    // index of a cell in a particle's stencil, which can reach past the edge of a periodic domain
+    pub(super) fn stencil_index(&self, x: i32, y: i32) -> usize {
+        self.index_at(
+            x.rem_euclid(self.width as i32) as usize,
+            y.rem_euclid(self.height as i32) as usize,
+        )
+    }
+
+    // columns and rows of the cells with all four neighbours on the grid.
+    // across periodic walls that is every cell, their neighbours wrap around with stencil_index.
+    fn neighboured_cells(&self) -> (Range<usize>, Range<usize>) {
+        let cells = |periodic: bool, extent: usize| {
+            if periodic {
+                0..extent
+            } else {
+                1..extent.saturating_sub(1)
+            }
+        };
+        (
+            cells(self.boundaries.periodic_x(), self.width),
+            cells(self.boundaries.periodic_y(), self.height),
+        )
     }
 
     pub(super) fn reset(&mut self) {
-        for mut cell in self.cells.iter_mut() {
+        for cell in self.cells.iter_mut() {
             cell.velocity = Vec2::ZERO;
             cell.mass = 0.0;
+            cell.previous_velocity = Vec2::ZERO;
+            cell.previous_field_velocity = [Vec2::ZERO; CONTACT_FIELDS];
+            cell.fluid_mass = 0.0;
+            cell.fluid_volume = 0.0;
+            cell.fluid_interfacial_tension = f32::INFINITY;
+            cell.field_mass = [0.0; CONTACT_FIELDS];
+            cell.field_velocity = [Vec2::ZERO; CONTACT_FIELDS];
+            cell.thermal_mass = 0.0;
+            cell.temperature = 0.0;
+            cell.conductivity = 0.0;
+            cell.temperature_change = 0.0;
         }
     }
 
-    pub(super) fn update(&mut self, dt: f32, gravity: f32) {
-        for (i, cell) in self.cells.iter_mut().enumerate() {
+    // explicit heat conduction between neighbouring cells that contain material.
+    // the conductance between two cells is capped by the lighter one to keep it stable for light cells and large dt,
+    // they then heat up slower than they should. the flow stays symmetric so no heat is lost.
+    // hot and cold sources hold the temperature of the cells they cover.
+    pub(super) fn conduct_heat(&mut self, dt: f32, sources: &[HeatSource]) {
+        for cell in self.cells.iter_mut() {
+            if cell.thermal_mass > 0.0 {
+                cell.temperature /= cell.thermal_mass;
+                cell.conductivity /= cell.thermal_mass;
+            }
+        }
+
+        let (columns, rows) = self.neighboured_cells();
+        for x in columns {
+            for y in rows.clone() {
+                let i = self.index_at(x, y);
+                let cell = self.cells[i];
+                if cell.thermal_mass <= 0.0 {
+                    continue;
+                }
+
+                let mut heat_flow = 0.0;
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let neighbour = self.cells[self.stencil_index(x as i32 + dx, y as i32 + dy)];
+                    if neighbour.thermal_mass > 0.0 {
+                        let conductivity = 0.5 * (cell.conductivity + neighbour.conductivity);
+                        let conductance = (conductivity * dt * HEAT_CONDUCTION_SPEEDUP).min(
+                            MAX_CONDUCTION_RATE * cell.thermal_mass.min(neighbour.thermal_mass),
+                        );
+                        heat_flow += conductance * (neighbour.temperature - cell.temperature);
+                    }
+                }
+                self.cells[i].temperature_change = heat_flow / cell.thermal_mass;
+            }
+        }
+
+        for source in sources {
+            let min = source.min * self.inv_dx();
+            let max = source.max * self.inv_dx();
+            let min_x = min.x.max(0.) as usize;
+            let min_y = min.y.max(0.) as usize;
+            let max_x = (max.x.max(0.) as usize).min(self.width.saturating_sub(1));
+            let max_y = (max.y.max(0.) as usize).min(self.height.saturating_sub(1));
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    let i = self.index_at(x, y);
+                    let cell = &mut self.cells[i];
+                    if cell.thermal_mass > 0.0 {
+                        cell.temperature_change = source.temperature - cell.temperature;
+                    }
+                }
+            }
+        }
+    }
+
+    // keep the velocity of the transferred momentum, call before forces are added
+    pub(super) fn store_previous_velocities(&mut self) {
+        for cell in self.cells.iter_mut().filter(|cell| cell.mass > 0.0) {
+            cell.previous_velocity = cell.velocity / cell.mass;
+            for (f, mass) in cell.field_mass.iter().enumerate() {
+                if *mass > 0.0 {
+                    cell.previous_field_velocity[f] = cell.field_velocity[f] / *mass;
+                }
+            }
+        }
+    }
+
+    // implicit particles, when given, have their elastic forces integrated with backward euler
+    // before the velocities are constrained. multi-field contact, when given its friction, is resolved
+    // there too, so the constraints come last and no field is left moving into a wall.
+    pub(super) fn update(
+        &mut self,
+        dt: f32,
+        gravity: f32,
+        implicit: Option<&[ImplicitParticle]>,
+        contact_friction: Option<f32>,
+        bodies: &mut [RigidBody],
+        colliders: &[(&Collider, Option<&KinematicMotion>)],
+    ) {
+        for cell in self.cells.iter_mut() {
             if cell.mass > 0.0 {
                 // convert momentum to velocity, apply gravity
                 cell.velocity *= 1.0 / cell.mass;
                 cell.velocity.y += dt * gravity;
 
-                // boundary conditions
-                let x = i / self.width;
-                let y = i % self.width;
-                if x < 2 {
-                    // can only stay in place or go right
-                    if cell.velocity.x < 0.0 {
-                        cell.velocity.x = 0.0;
+                // same for each contact field
+                for (mass, velocity) in cell.field_mass.iter().zip(cell.field_velocity.iter_mut()) {
+                    if *mass > 0.0 {
+                        *velocity *= 1.0 / mass;
+                        velocity.y += dt * gravity;
                     }
                 }
-                if x > self.width - 3 {
-                    // can only stay in place or go left
-                    if cell.velocity.x > 0.0 {
-                        cell.velocity.x = 0.0;
+            }
+        }
+
+        if let Some(particles) = implicit {
+            self.solve_implicit(dt, particles);
+        }
+        if let Some(friction) = contact_friction {
+            self.resolve_contact(friction);
+        }
+
+        let width = self.width;
+        let height = self.height;
+        let dx = self.dx;
+        let boundaries = self.boundaries;
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            if cell.mass > 0.0 {
+                let x = i / height;
+                let y = i % height;
+
+                // rigid bodies stop the grid moving into them, and take the momentum that was stopped
+                let node = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * dx;
+                for body in bodies.iter_mut() {
+                    if let Some(normal) = body.surface_normal(node, dx) {
+                        let body_velocity = body.velocity_at(node);
+                        let removed =
+                            remove_approaching_velocity(&mut cell.velocity, body_velocity, normal);
+                        body.add_impulse(node, removed * cell.mass);
+                        for velocity in cell.field_velocity.iter_mut() {
+                            remove_approaching_velocity(velocity, body_velocity, normal);
+                        }
                     }
                 }
-                if y < 2 {
-                    // can only stay in place or go up
-                    if cell.velocity.y < 0.0 {
-                        cell.velocity.y = 0.0;
+
+                // colliders, moving ones drag the grid along with them
+                for (collider, motion) in colliders {
+                    let local = motion.map_or(node, |m| m.to_rest_position(node));
+                    if collider.shape.distance(local) < dx {
+                        let mut normal = collider.shape.normal(local, dx);
+                        let mut surface_velocity = Vec2::ZERO;
+                        if let Some(motion) = motion {
+                            normal = motion.rotate(normal);
+                            surface_velocity = motion.velocity_at(node);
+                        }
+                        for velocity in std::iter::once(&mut cell.velocity)
+                            .chain(cell.field_velocity.iter_mut())
+                        {
+                            constrain_velocity(
+                                velocity,
+                                normal,
+                                surface_velocity,
+                                collider.boundary,
+                                collider.friction,
+                            );
+                        }
                     }
                 }
-                if y > self.width - 3 {
-                    // can only stay in place or go down
-                    if cell.velocity.y > 0.0 {
-                        cell.velocity.y = 0.0;
+
+                boundaries.apply(&mut cell.velocity, x, y, width, height);
+                for velocity in cell.field_velocity.iter_mut() {
+                    boundaries.apply(velocity, x, y, width, height);
+                }
+            }
+        }
+    }
+
+    // multi-field contact (Bardenhagen et al. 2000).
+    // where several fields share a cell each keeps its own velocity while separating.
+    // a field moving into the others loses its approaching velocity relative to the shared center of mass velocity,
+    // and coulomb friction slows its sliding.
+    fn resolve_contact(&mut self, friction: f32) {
+        let (columns, rows) = self.neighboured_cells();
+        for x in columns {
+            for y in rows.clone() {
+                let i = self.index_at(x, y);
+                let cell = self.cells[i];
+                if cell.field_mass.iter().filter(|m| **m > 0.0).count() < 2 {
+                    continue;
+                }
+
+                for (f, field_mass) in cell.field_mass.iter().enumerate() {
+                    if *field_mass <= 0.0 {
+                        continue;
                     }
+
+                    // outward surface normal of the field from its mass gradient
+                    let mass_at = |dx: i32, dy: i32| {
+                        self.cells[self.stencil_index(x as i32 + dx, y as i32 + dy)].field_mass[f]
+                    };
+                    let mass_gradient = Vec2::new(
+                        mass_at(1, 0) - mass_at(-1, 0),
+                        mass_at(0, 1) - mass_at(0, -1),
+                    );
+                    let normal = -mass_gradient.normalize_or_zero();
+
+                    let relative_velocity = cell.field_velocity[f] - cell.velocity;
+                    let approaching_speed = relative_velocity.dot(normal);
+                    if approaching_speed <= 0.0 {
+                        continue;
+                    }
+
+                    let tangential = relative_velocity - normal * approaching_speed;
+                    let tangential_speed = tangential.length();
+                    let sliding = if tangential_speed > 0.0 {
+                        tangential
+                            * (1.0 - f32::min(1.0, friction * approaching_speed / tangential_speed))
+                    } else {
+                        Vec2::ZERO
+                    };
+                    self.cells[i].field_velocity[f] = cell.velocity + sliding;
                 }
             }
         }
     }
 }
 
-pub(super) fn reset_grid(mut grid: ResMut<Grid>) {
-    grid.reset();
+// remove the part of the velocity moving into a surface with the given outward normal and velocity.
+// returns the velocity that was removed.
+fn remove_approaching_velocity(velocity: &mut Vec2, surface_velocity: Vec2, normal: Vec2) -> Vec2 {
+    let approaching_speed = (*velocity - surface_velocity).dot(normal);
+    if approaching_speed >= 0.0 {
+        return Vec2::ZERO;
+    }
+    let removed = normal * approaching_speed;
+    *velocity -= removed;
+    removed
 }
 
-pub(super) fn quadratic_interpolation_weights(cell_diff: Vec2) -> [Vec2; 3] {
-    [
-        Vec2::new(
-            0.5 * f32::powi(0.5 - cell_diff.x, 2),
-            0.5 * f32::powi(0.5 - cell_diff.y, 2),
-        ),
-        Vec2::new(
-            0.75 - f32::powi(cell_diff.x, 2),
-            0.75 - f32::powi(cell_diff.y, 2),
-        ),
-        Vec2::new(
-            0.5 * f32::powi(0.5 + cell_diff.x, 2),
-            0.5 * f32::powi(0.5 + cell_diff.y, 2),
-        ),
-    ]
+pub(super) fn reset_grid(mut grid: ResMut<Grid>) {
+    grid.reset();
 }
 
+// outer product of a weighted velocity and the distance from particle to cell in meters, or of a velocity
+// and a weight gradient. summed over the stencil these are B from APIC and the velocity gradient.
 pub(super) fn weighted_velocity_and_cell_dist_to_term(
     weighted_velocity: Vec2,
     cell_dist: Vec2,
@@ -114,3 +384,140 @@ pub(super) fn weighted_velocity_and_cell_dist_to_term(
         ),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::DEFAULT_CONTACT_FRICTION;
+
+    // a light hot cell next to a heavy cold one, summed the way P2G leaves them
+    fn two_cells(conductivity: f32) -> (Grid, usize, usize) {
+        let mut grid = Grid::new(Vec2::splat(8.), 1.);
+        let hot = grid.index_at(3, 3);
+        let cold = grid.index_at(4, 3);
+        for (i, thermal_mass, temperature) in [(hot, 0.5, 400.), (cold, 4., 300.)] {
+            let cell = &mut grid.cells[i];
+            cell.thermal_mass = thermal_mass;
+            cell.temperature = thermal_mass * temperature;
+            cell.conductivity = thermal_mass * conductivity;
+        }
+        (grid, hot, cold)
+    }
+
+    #[test]
+    fn conduction_conserves_heat() {
+        for conductivity in [0.1, 10., 1e6] {
+            let (mut grid, hot, cold) = two_cells(conductivity);
+            grid.conduct_heat(0.01, &[]);
+            let (hot, cold) = (grid.cells[hot], grid.cells[cold]);
+            assert!(hot.temperature_change < 0.);
+            let heat_change = hot.thermal_mass * hot.temperature_change
+                + cold.thermal_mass * cold.temperature_change;
+            assert!(
+                heat_change.abs() < 1e-3,
+                "{} heat created at conductivity {}",
+                heat_change,
+                conductivity
+            );
+        }
+    }
+
+    #[test]
+    fn conduction_stays_bounded_for_stiff_steps() {
+        for conductivity in [0.1, 10., 1e6] {
+            let (mut grid, hot, cold) = two_cells(conductivity);
+            grid.conduct_heat(1., &[]);
+            let hot = grid.cells[hot].temperature + grid.cells[hot].temperature_change;
+            let cold = grid.cells[cold].temperature + grid.cells[cold].temperature_change;
+            assert!(
+                300. <= cold && cold <= hot && hot <= 400.,
+                "cells at {} and {} overshot at conductivity {}",
+                hot,
+                cold,
+                conductivity
+            );
+        }
+    }
+
+    #[test]
+    fn conduction_wraps_around_periodic_walls() {
+        for periodic in [false, true] {
+            let mut grid = Grid::new(Vec2::splat(8.), 1.);
+            if periodic {
+                grid.boundaries.left = WallBoundary::Periodic;
+                grid.boundaries.right = WallBoundary::Periodic;
+            }
+            let hot = grid.index_at(0, 3);
+            let cold = grid.index_at(7, 3);
+            for (i, temperature) in [(hot, 400.), (cold, 300.)] {
+                let cell = &mut grid.cells[i];
+                cell.thermal_mass = 1.;
+                cell.temperature = temperature;
+                cell.conductivity = 1.;
+            }
+            grid.conduct_heat(0.01, &[]);
+            assert_eq!(
+                grid.cells[cold].temperature_change > 0.,
+                periodic,
+                "heat crossing the left and right walls with periodic {}",
+                periodic
+            );
+        }
+    }
+
+    #[test]
+    fn contact_stops_fields_approaching_each_other() {
+        for contact_friction in [None, Some(0.), Some(DEFAULT_CONTACT_FRICTION)] {
+            // field 0 comes in from the left and field 1 from the right, meeting in the middle cell
+            let mut grid = Grid::new(Vec2::splat(8.), 1.);
+            let middle = grid.index_at(3, 3);
+            for (x, field, momentum) in [(2, 0, 1.), (3, 0, 1.), (3, 1, -1.), (4, 1, -1.)] {
+                let i = grid.index_at(x, 3);
+                let cell = &mut grid.cells[i];
+                cell.mass += 1.;
+                cell.velocity.x += momentum;
+                cell.field_mass[field] += 1.;
+                cell.field_velocity[field].x += momentum;
+            }
+            grid.update(0.01, 0., None, contact_friction, &mut [], &[]);
+
+            let [left, right, ..] = grid.cells[middle].field_velocity;
+            let approaching_speed = left.x - right.x;
+            if contact_friction.is_some() {
+                assert!(
+                    approaching_speed.abs() < 1e-6,
+                    "fields still approach at {} with friction {:?}",
+                    approaching_speed,
+                    contact_friction
+                );
+            } else {
+                assert_eq!(approaching_speed, 2., "fields pass through without contact");
+            }
+        }
+    }
+
+    #[test]
+    fn rigid_body_takes_the_momentum_it_stops() {
+        let mut grid = Grid::new(Vec2::splat(16.), 1.);
+        let approaching = grid.index_at(5, 8);
+        let leaving = grid.index_at(10, 8);
+        for (i, velocity) in [(approaching, 2.), (leaving, 1.)] {
+            let cell = &mut grid.cells[i];
+            cell.mass = 3.;
+            cell.velocity = Vec2::new(3. * velocity, 0.);
+        }
+        // level with both nodes, so pushing it head on doesn't spin it
+        let mut body = RigidBody::new_box(Vec2::new(8., 8.5), Vec2::splat(2.), 100., false);
+
+        grid.update(0.01, 0., None, None, std::slice::from_mut(&mut body), &[]);
+
+        assert_eq!(grid.cells[approaching].velocity, Vec2::ZERO);
+        assert_eq!(grid.cells[leaving].velocity, Vec2::new(1., 0.));
+        assert!(
+            (body.impulse - Vec2::new(6., 0.)).length() < 1e-5,
+            "body took {} of the stopped momentum",
+            body.impulse
+        );
+        assert!(body.angular_impulse.abs() < 1e-5);
+    }
+}
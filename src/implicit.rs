@@ -0,0 +1,245 @@
+use bevy::math::{Mat2, Vec2};
+
+use crate::defaults::{IMPLICIT_MAX_ITERATIONS, IMPLICIT_TOLERANCE};
+use crate::grid::*;
+use crate::kernel::MAX_STENCIL_SIZE;
+
+// a hyperelastic particle as the implicit grid solve sees it
+#[derive(Clone, Copy)]
+pub(super) struct ImplicitParticle {
+    pub(super) volume: f32,
+    // stress_differential of the particle for each of unit_deformation_steps, it is linear so these span it
+    pub(super) stress_differentials: [Mat2; 4],
+    // cell index and weight gradient in 1/m for each cell of the stencil, the first stencil_size are used
+    pub(super) stencil: [(usize, Vec2); MAX_STENCIL_SIZE],
+    pub(super) stencil_size: usize,
+}
+
+impl ImplicitParticle {
+    fn stencil(&self) -> &[(usize, Vec2)] {
+        &self.stencil[..self.stencil_size]
+    }
+
+    fn stress_differential(&self, deformation_step: Mat2) -> Mat2 {
+        self.stress_differentials[0] * deformation_step.x_axis.x
+            + self.stress_differentials[1] * deformation_step.x_axis.y
+            + self.stress_differentials[2] * deformation_step.y_axis.x
+            + self.stress_differentials[3] * deformation_step.y_axis.y
+    }
+}
+
+// one unit matrix per entry, in the order x_axis.x, x_axis.y, y_axis.x, y_axis.y
+pub(super) fn unit_deformation_steps() -> [Mat2; 4] {
+    [
+        Mat2::from_cols(Vec2::X, Vec2::ZERO),
+        Mat2::from_cols(Vec2::Y, Vec2::ZERO),
+        Mat2::from_cols(Vec2::ZERO, Vec2::X),
+        Mat2::from_cols(Vec2::ZERO, Vec2::Y),
+    ]
+}
+
+// particles gathered each step for the implicit solve, emptied once the grid is updated
+#[derive(Default)]
+pub(super) struct ImplicitParticles(pub(super) Vec<ImplicitParticle>);
+
+impl Grid {
+    // backward euler for the elastic forces of the gathered particles (Stomakhin et al. 2013, section 9).
+    // the explicit forces are already in the grid velocities v*, one newton step then solves
+    // (M + dt^2 H) v = M v* with matrix free conjugate gradients, H the energy hessian.
+    // only cells the particles touch take part, the rest keep their explicit velocity.
+    // returns whether conjugate gradients reached IMPLICIT_TOLERANCE.
+    pub(super) fn solve_implicit(&mut self, dt: f32, particles: &[ImplicitParticle]) -> bool {
+        if particles.is_empty() {
+            return true;
+        }
+        let masses: Vec<f32> = self.cells.iter().map(|cell| cell.mass).collect();
+        let mut active = vec![false; self.cells.len()];
+        for particle in particles {
+            for &(i, _) in particle.stencil() {
+                active[i] |= masses[i] > 0.0;
+            }
+        }
+
+        // (M + dt^2 H) v, the force differential of each particle is volume * dP F^T * grad w
+        let apply = |v: &[Vec2], out: &mut [Vec2]| {
+            for (i, out) in out.iter_mut().enumerate() {
+                *out = if active[i] {
+                    v[i] * masses[i]
+                } else {
+                    Vec2::ZERO
+                };
+            }
+            for particle in particles {
+                let mut velocity_gradient = Mat2::ZERO;
+                for &(i, weight_gradient) in particle.stencil() {
+                    if active[i] {
+                        velocity_gradient +=
+                            weighted_velocity_and_cell_dist_to_term(v[i], weight_gradient);
+                    }
+                }
+                let stress = particle.stress_differential(velocity_gradient * dt);
+                for &(i, weight_gradient) in particle.stencil() {
+                    if active[i] {
+                        out[i] += stress * weight_gradient * (particle.volume * dt);
+                    }
+                }
+            }
+        };
+        let dot = |a: &[Vec2], b: &[Vec2]| -> f32 { a.iter().zip(b).map(|(a, b)| a.dot(*b)).sum() };
+
+        // start from the explicit velocities
+        let explicit: Vec<Vec2> = self
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| if active[i] { cell.velocity } else { Vec2::ZERO })
+            .collect();
+        let rhs: Vec<Vec2> = explicit.iter().zip(&masses).map(|(v, m)| *v * *m).collect();
+        let mut velocity = explicit.clone();
+        let mut product = vec![Vec2::ZERO; velocity.len()];
+        apply(&velocity, &mut product);
+        let mut residual: Vec<Vec2> = rhs.iter().zip(&product).map(|(b, a)| *b - *a).collect();
+        let mut direction = residual.clone();
+        let mut residual_squared = dot(&residual, &residual);
+        let threshold = IMPLICIT_TOLERANCE * IMPLICIT_TOLERANCE * dot(&rhs, &rhs);
+
+        let mut converged = residual_squared <= threshold;
+        for _ in 0..IMPLICIT_MAX_ITERATIONS {
+            if converged {
+                break;
+            }
+            apply(&direction, &mut product);
+            let curvature = dot(&direction, &product);
+            // the hessian of an inverted or badly compressed element can be indefinite, keep what we have
+            if curvature <= 0.0 {
+                break;
+            }
+            let alpha = residual_squared / curvature;
+            for (v, d) in velocity.iter_mut().zip(&direction) {
+                *v += *d * alpha;
+            }
+            for (r, p) in residual.iter_mut().zip(&product) {
+                *r -= *p * alpha;
+            }
+            let next_residual_squared = dot(&residual, &residual);
+            let beta = next_residual_squared / residual_squared;
+            for (d, r) in direction.iter_mut().zip(&residual) {
+                *d = *r + *d * beta;
+            }
+            residual_squared = next_residual_squared;
+            converged = residual_squared <= threshold;
+        }
+
+        // contact fields get the same change as the cell
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            if active[i] {
+                let change = velocity[i] - explicit[i];
+                cell.velocity = velocity[i];
+                for (mass, field_velocity) in
+                    cell.field_mass.iter().zip(cell.field_velocity.iter_mut())
+                {
+                    if *mass > 0.0 {
+                        *field_velocity += change;
+                    }
+                }
+            }
+        }
+        converged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::*;
+    use crate::defaults::DEFAULT_DT;
+    use crate::world::TransferScheme;
+
+    // steel particles at rest at the given positions, with their mass scattered onto the grid
+    fn steel_particles(grid: &mut Grid, positions: &[Vec2]) -> Vec<ImplicitParticle> {
+        let model = steel_properties();
+        let volume = 0.25 * grid.dx * grid.dx;
+        positions
+            .iter()
+            .map(|position| {
+                let stencil = grid.kernel.stencil(grid, *position);
+                let mut cells = [(0, Vec2::ZERO); MAX_STENCIL_SIZE];
+                for gx in 0..stencil.width {
+                    for gy in 0..stencil.width {
+                        let (x, y) = stencil.cell(gx, gy);
+                        let i = grid.stencil_index(x, y);
+                        grid.cells[i].mass += stencil.weight(gx, gy) * STEEL_DENSITY * volume;
+                        cells[stencil.slot(gx, gy)] = (
+                            i,
+                            stencil.weight_gradient(
+                                grid,
+                                TransferScheme::MlsMpm,
+                                gx,
+                                gy,
+                                grid.node_position(x, y) - *position,
+                            ),
+                        );
+                    }
+                }
+                ImplicitParticle {
+                    volume,
+                    stress_differentials: unit_deformation_steps()
+                        .map(|step| model.stress_differential(step)),
+                    stencil: cells,
+                    stencil_size: stencil.width * stencil.width,
+                }
+            })
+            .collect()
+    }
+
+    fn kinetic_energy(grid: &Grid) -> f32 {
+        grid.cells
+            .iter()
+            .map(|cell| 0.5 * cell.mass * cell.velocity.length_squared())
+            .sum()
+    }
+
+    #[test]
+    fn conjugate_gradients_converge_for_a_single_particle() {
+        let mut grid = Grid::new(Vec2::splat(8.), 1.);
+        let particles = steel_particles(&mut grid, &[Vec2::new(4.2, 3.7)]);
+        let corner = grid.index_at(3, 2);
+        grid.cells[corner].velocity = Vec2::new(1., -0.5);
+
+        assert!(grid.solve_implicit(DEFAULT_DT, &particles));
+        assert!(grid.cells[corner].velocity.length() < Vec2::new(1., -0.5).length());
+    }
+
+    #[test]
+    fn stiff_solid_at_rest_stays_stable_for_large_steps() {
+        for dt in [DEFAULT_DT, 0.1, 1.] {
+            let mut grid = Grid::new(Vec2::splat(16.), 1.);
+            let positions: Vec<Vec2> = (0..8)
+                .flat_map(|x| {
+                    (0..8).map(move |y| Vec2::new(4.25, 4.25) + Vec2::new(x as f32, y as f32) * 0.5)
+                })
+                .collect();
+            let particles = steel_particles(&mut grid, &positions);
+
+            // knock one node of the block, explicit steel would blow up long before dt = 1
+            let knocked = grid.index_at(6, 6);
+            grid.cells[knocked].velocity = Vec2::new(1., 0.5);
+            let energy = kinetic_energy(&grid);
+
+            grid.solve_implicit(dt, &particles);
+            assert!(
+                grid.cells.iter().all(|cell| cell.velocity.is_finite()),
+                "velocities blew up at dt {}",
+                dt
+            );
+            // backward euler only takes energy out
+            assert!(
+                kinetic_energy(&grid) <= energy * 1.001,
+                "kinetic energy grew from {} to {} at dt {}",
+                energy,
+                kinetic_energy(&grid),
+                dt
+            );
+        }
+    }
+}
@@ -7,8 +7,10 @@ use bevy_egui::{egui, EguiContext, EguiSettings};
 
 use crate::{grid, spawn_particles, ParticleSpawnerInfo, SpawnerPattern};
 
+use super::boundaries::WallBoundary;
 use super::components::*;
 use super::defaults::*;
+use super::kernel::Kernel;
 use super::world::*;
 
 #[derive(Default)]
@@ -17,6 +19,21 @@ pub(super) struct ClickAndDragState {
     source_pos: Vec2,
 }
 
+// fluid dumped by right clicking
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(super) enum BucketMaterial {
+    #[default]
+    Water,
+    Oil,
+    Honey,
+    Mercury,
+    Mud,
+    Toothpaste,
+    Oobleck,
+    Slime,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(super) fn handle_inputs(
     mut commands: Commands,
     windows: Res<Windows>,
@@ -28,23 +45,24 @@ pub(super) fn handle_inputs(
     mut toggle_scale_factor: Local<Option<bool>>,
     mut world: ResMut<WorldState>,
     mut spawner_drag: Local<ClickAndDragState>,
+    mut bucket_material: Local<BucketMaterial>,
     mut particles: Query<(Entity, &Position, &mut Velocity, &Mass), With<ParticleTag>>,
     // todo also reset all known spawners to the current set spawn patern.
-    grid: Res<grid::Grid>,
+    mut grid: ResMut<grid::Grid>,
 ) {
     let window = windows.get_primary().unwrap();
     if let Some(win_pos) = window.cursor_position() {
         // cursor is inside the window.
-        // translate window position to grid position
-        let size = Vec2::new(window.width() as f32, window.height() as f32);
-        let scale = f32::min(size.x, size.y) / grid.width as f32;
-        let grid_pos = win_pos / scale;
+        // translate window position to grid position, in meters
+        let size = Vec2::new(window.width(), window.height());
+        let scale = f32::min(size.x / grid.width as f32, size.y / grid.height as f32);
+        let grid_pos = win_pos / scale * grid.dx;
 
         // if particle is near cursor, push it away.
         particles.par_for_each_mut(PAR_BATCH_SIZE, |(_, position, mut velocity, _)| {
             let dist = Vec2::new(position.0.x - grid_pos.x, position.0.y - grid_pos.y);
 
-            let mouse_radius = 6.;
+            let mouse_radius = 6. * grid.dx;
 
             if dist.dot(dist) < mouse_radius * mouse_radius {
                 let norm_factor = dist.length() / mouse_radius;
@@ -66,6 +84,7 @@ pub(super) fn handle_inputs(
             let si = &ParticleSpawnerInfo {
                 created_at: world.current_tick,
                 pattern: SpawnerPattern::Triangle,
+                particles_per_cell: 16.,
                 spawn_frequency: 999999999, // todo special value to spawn once only.
                 max_particles: 500000,
                 particle_duration: 20000,
@@ -73,12 +92,17 @@ pub(super) fn handle_inputs(
                 particle_velocity: grid_pos - spawner_drag.source_pos,
                 particle_velocity_random_vec_a: Default::default(),
                 particle_velocity_random_vec_b: Default::default(),
-                particle_mass: 1.0,
+                particle_density: STEEL_DENSITY,
+                particle_temperature: DEFAULT_TEMPERATURE,
+                particle_thermal_properties: steel_thermal_properties(),
+                particle_contact_field: STEEL_CONTACT_FIELD,
+                particle_phase_change: None,
             };
 
+            // fixed corotated stays stable when arrowheads get crushed against the walls
             spawn_particles(
                 si,
-                steel_properties(),
+                corotated_steel_properties(),
                 &mut commands,
                 asset_server.load("steel_particle.png"),
                 &world,
@@ -86,11 +110,12 @@ pub(super) fn handle_inputs(
             );
         }
 
-        // can right click to dump a bucket of water.
+        // can right click to dump a bucket of water (or the fluid selected in controls).
         if btn.just_pressed(MouseButton::Right) {
             let si = &ParticleSpawnerInfo {
                 created_at: world.current_tick,
                 pattern: SpawnerPattern::Cube,
+                particles_per_cell: 1.,
                 spawn_frequency: 999999999, // todo special value to spawn once only.
                 max_particles: 500000,
                 particle_duration: 20000,
@@ -98,18 +123,86 @@ pub(super) fn handle_inputs(
                 particle_velocity: Vec2::new(0., -40.),
                 particle_velocity_random_vec_a: Default::default(),
                 particle_velocity_random_vec_b: Default::default(),
-                particle_mass: 0.75,
+                particle_density: water_properties().rest_density,
+                particle_temperature: DEFAULT_TEMPERATURE,
+                particle_thermal_properties: water_thermal_properties(),
+                particle_contact_field: FLUID_CONTACT_FIELD,
+                particle_phase_change: None,
+            };
+
+            // buckets hold the same number of particles, so denser fluids get heavier particles
+            let bucket_of = |fluid: NewtonianFluidModel| ParticleSpawnerInfo {
+                particle_density: fluid.rest_density,
+                ..si.clone()
             };
 
             // todo value to set size/width of spawned objects
-            spawn_particles(
-                si,
-                water_properties(),
-                &mut commands,
-                asset_server.load("liquid_particle.png"),
-                &world,
-                &grid,
-            );
+            match *bucket_material {
+                BucketMaterial::Water => spawn_particles(
+                    si,
+                    water_properties(),
+                    &mut commands,
+                    asset_server.load("liquid_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Oil => spawn_particles(
+                    &bucket_of(oil_properties()),
+                    oil_properties(),
+                    &mut commands,
+                    asset_server.load("oil_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Honey => spawn_particles(
+                    &bucket_of(honey_properties()),
+                    honey_properties(),
+                    &mut commands,
+                    asset_server.load("honey_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Mercury => spawn_particles(
+                    &bucket_of(mercury_properties()),
+                    mercury_properties(),
+                    &mut commands,
+                    asset_server.load("mercury_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Mud => spawn_particles(
+                    si,
+                    mud_properties(),
+                    &mut commands,
+                    asset_server.load("mud_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Toothpaste => spawn_particles(
+                    si,
+                    toothpaste_properties(),
+                    &mut commands,
+                    asset_server.load("paste_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Oobleck => spawn_particles(
+                    si,
+                    oobleck_properties(),
+                    &mut commands,
+                    asset_server.load("paste_particle.png"),
+                    &world,
+                    &grid,
+                ),
+                BucketMaterial::Slime => spawn_particles(
+                    si,
+                    slime_properties(),
+                    &mut commands,
+                    asset_server.load("slime_particle.png"),
+                    &world,
+                    &grid,
+                ),
+            }
         }
 
         egui::Window::new("Controls").show(egui_context.ctx_mut(), |ui| {
@@ -119,6 +212,7 @@ pub(super) fn handle_inputs(
                 });
                 world.dt = DEFAULT_DT;
                 world.gravity = DEFAULT_GRAVITY;
+                world.simulation_speed = DEFAULT_SIMULATION_SPEED;
                 return;
             };
             if ui.button("(G)ravity toggle").clicked() || keys.just_pressed(KeyCode::G) {
@@ -129,8 +223,71 @@ pub(super) fn handle_inputs(
             // slider for gravity
             ui.add(egui::Slider::new(&mut world.gravity, -10.0..=10.).text("gravity"));
 
-            // slider for DT.
-            ui.add(egui::Slider::new(&mut world.dt, 0.0001..=0.01).text("dt"));
+            // backward euler for hyperelastic solids, stable at much larger dt
+            ui.checkbox(&mut world.implicit, "implicit solids");
+
+            // slider for DT, or the range it is picked from each step
+            ui.checkbox(&mut world.adaptive_dt, "adaptive dt");
+            if world.adaptive_dt {
+                ui.label(format!("dt: {:.5}", world.dt));
+                ui.add(egui::Slider::new(&mut world.min_dt, 0.00001..=0.01).text("min dt"));
+                ui.add(egui::Slider::new(&mut world.max_dt, 0.0001..=0.05).text("max dt"));
+                ui.add(egui::Slider::new(&mut world.cfl, 0.05..=1.).text("cfl"));
+                world.max_dt = world.max_dt.max(world.min_dt);
+            } else {
+                let max_dt = if world.implicit { 0.1 } else { 0.01 };
+                ui.add(egui::Slider::new(&mut world.dt, 0.0001..=max_dt).text("dt"));
+            }
+
+            // simulated seconds per second, run in steps of dt
+            ui.add(egui::Slider::new(&mut world.simulation_speed, 0.01..=1.).text("speed"));
+            ui.add(egui::Slider::new(&mut world.max_substeps, 1..=64).text("max steps per frame"));
+            ui.label(format!("steps last frame: {}", world.substeps));
+
+            // how velocities move between particles and grid
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut world.transfer, TransferScheme::Pic, "PIC");
+                ui.radio_value(&mut world.transfer, TransferScheme::PicFlip, "PIC/FLIP");
+                ui.radio_value(&mut world.transfer, TransferScheme::Apic, "APIC");
+                ui.radio_value(&mut world.transfer, TransferScheme::MlsMpm, "MLS-MPM");
+            });
+            if world.transfer == TransferScheme::PicFlip {
+                ui.add(egui::Slider::new(&mut world.flip_ratio, 0.0..=1.).text("flip ratio"));
+            }
+
+            // interpolation between particles and grid, wider is smoother but slower
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut grid.kernel, Kernel::Linear, "linear");
+                ui.radio_value(&mut grid.kernel, Kernel::Quadratic, "quadratic");
+                ui.radio_value(&mut grid.kernel, Kernel::Cubic, "cubic");
+            });
+
+            // let materials slide and separate instead of sticking together
+            ui.checkbox(&mut world.multi_field_contact, "multi-field contact");
+            ui.add(egui::Slider::new(&mut world.contact_friction, 0.0..=1.).text("friction"));
+
+            // what happens to material reaching each wall
+            let boundaries = &mut grid.boundaries;
+            wall_boundary_selector(ui, "left", &mut boundaries.left, &mut boundaries.right);
+            wall_boundary_selector(ui, "right", &mut boundaries.right, &mut boundaries.left);
+            wall_boundary_selector(ui, "bottom", &mut boundaries.bottom, &mut boundaries.top);
+            wall_boundary_selector(ui, "top", &mut boundaries.top, &mut boundaries.bottom);
+
+            // fluid to dump with right click
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Water, "water");
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Oil, "oil");
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Honey, "honey");
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Mercury, "mercury");
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Mud, "mud");
+                ui.radio_value(
+                    &mut *bucket_material,
+                    BucketMaterial::Toothpaste,
+                    "toothpaste",
+                );
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Oobleck, "oobleck");
+                ui.radio_value(&mut *bucket_material, BucketMaterial::Slime, "slime");
+            });
 
             // toggle hiDPI with '/'
             if keys.just_pressed(KeyCode::Slash) || toggle_scale_factor.is_none() {
@@ -153,3 +310,34 @@ pub(super) fn handle_inputs(
         });
     };
 }
+
+// radio buttons for one wall. particles leaving through a periodic wall come in through the opposite one,
+// so that is switched along with it.
+fn wall_boundary_selector(
+    ui: &mut egui::Ui,
+    label: &str,
+    wall: &mut WallBoundary,
+    opposite: &mut WallBoundary,
+) {
+    let was_periodic = *wall == WallBoundary::Periodic;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.radio_value(wall, WallBoundary::Sticky, "sticky");
+        ui.radio_value(wall, WallBoundary::Slip, "slip");
+        let separate = matches!(wall, WallBoundary::Separate { .. });
+        if ui.radio(separate, "separate").clicked() && !separate {
+            *wall = WallBoundary::Separate { friction: 0. };
+        }
+        ui.radio_value(wall, WallBoundary::Outflow, "outflow");
+        ui.radio_value(wall, WallBoundary::Periodic, "periodic");
+    });
+    if let WallBoundary::Separate { friction } = wall {
+        ui.add(egui::Slider::new(friction, 0.0..=1.).text(format!("{} friction", label)));
+    }
+
+    if *wall == WallBoundary::Periodic {
+        *opposite = WallBoundary::Periodic;
+    } else if was_periodic {
+        *opposite = *wall;
+    }
+}
@@ -0,0 +1,296 @@
+use bevy::math::Vec2;
+
+use crate::grid::Grid;
+use crate::world::TransferScheme;
+
+// widest stencil in cells per axis, and cells in it. per particle grid contributions are stored in arrays this size
+pub(super) const MAX_STENCIL_WIDTH: usize = 4;
+pub(super) const MAX_STENCIL_SIZE: usize = MAX_STENCIL_WIDTH * MAX_STENCIL_WIDTH;
+
+// B-spline interpolation kernel between particles and grid.
+// wider kernels are smoother and lose less to grid noise, but every transfer touches more cells.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(super) enum Kernel {
+    // 2x2 cells. cheapest, but weight gradients jump as particles cross cell centers
+    Linear,
+    // 3x3 cells
+    Quadratic,
+    // 4x4 cells
+    Cubic,
+}
+
+impl Kernel {
+    // cells per axis
+    pub(super) fn width(self) -> usize {
+        match self {
+            Kernel::Linear => 2,
+            Kernel::Quadratic => 3,
+            Kernel::Cubic => 4,
+        }
+    }
+
+    // closest a particle can get to the edge of the grid, in cells, with its whole stencil still on the grid
+    pub(super) fn reach(self) -> f32 {
+        match self {
+            Kernel::Linear => 0.5,
+            Kernel::Quadratic => 1.0,
+            Kernel::Cubic => 1.5,
+        }
+    }
+
+    // D^-1 from APIC in units of 1/dx^2. linear weights have no constant D,
+    // but their D^-1 * weight * cell_dist is exactly the weight gradient.
+    pub(super) fn d_inverse(self) -> Option<f32> {
+        match self {
+            Kernel::Linear => None,
+            Kernel::Quadratic => Some(4.0),
+            Kernel::Cubic => Some(3.0),
+        }
+    }
+
+    // the cells around a particle at position (in meters) with their weights
+    pub(super) fn stencil(self, grid: &Grid, position: Vec2) -> Stencil {
+        // position relative to the cell centers, in cells
+        let node_position = position * grid.inv_dx() - Vec2::splat(0.5);
+        let mut weights = [Vec2::ZERO; MAX_STENCIL_WIDTH];
+        let mut weight_derivatives = [Vec2::ZERO; MAX_STENCIL_WIDTH];
+        let origin = match self {
+            Kernel::Linear => {
+                let base = node_position.floor();
+                let fraction = node_position - base;
+                weights[..2].copy_from_slice(&linear_interpolation_weights(fraction));
+                weight_derivatives[..2].copy_from_slice(&[Vec2::splat(-1.0), Vec2::splat(1.0)]);
+                base
+            }
+            Kernel::Quadratic => {
+                let base = (node_position + Vec2::splat(0.5)).floor();
+                let cell_diff = node_position - base;
+                weights[..3].copy_from_slice(&quadratic_interpolation_weights(cell_diff));
+                weight_derivatives[..3]
+                    .copy_from_slice(&quadratic_interpolation_weight_derivatives(cell_diff));
+                base - Vec2::ONE
+            }
+            Kernel::Cubic => {
+                let base = node_position.floor();
+                let fraction = node_position - base;
+                weights.copy_from_slice(&cubic_interpolation_weights(fraction));
+                weight_derivatives
+                    .copy_from_slice(&cubic_interpolation_weight_derivatives(fraction));
+                base - Vec2::ONE
+            }
+        };
+        Stencil {
+            d_inverse: self.d_inverse().map(|d| d * grid.inv_dx() * grid.inv_dx()),
+            origin_x: origin.x as i32,
+            origin_y: origin.y as i32,
+            weights,
+            weight_derivatives,
+            width: self.width(),
+        }
+    }
+}
+
+// the cells a particle exchanges with, indexed by (gx, gy) from 0 to width
+pub(super) struct Stencil {
+    // D^-1 in 1/m^2, None for linear weights
+    d_inverse: Option<f32>,
+    // lowest cell, which can be outside a periodic domain
+    origin_x: i32,
+    origin_y: i32,
+    // weights along each axis, and their derivatives with respect to the particle position in 1/cell
+    weights: [Vec2; MAX_STENCIL_WIDTH],
+    weight_derivatives: [Vec2; MAX_STENCIL_WIDTH],
+    pub(super) width: usize,
+}
+
+impl Stencil {
+    pub(super) fn weight(&self, gx: usize, gy: usize) -> f32 {
+        self.weights[gx].x * self.weights[gy].y
+    }
+
+    // cell coordinates of stencil cell (gx, gy), see Grid::stencil_index
+    pub(super) fn cell(&self, gx: usize, gy: usize) -> (i32, i32) {
+        (self.origin_x + gx as i32, self.origin_y + gy as i32)
+    }
+
+    // slot of stencil cell (gx, gy) in a particle's contribution array
+    pub(super) fn slot(&self, gx: usize, gy: usize) -> usize {
+        gx + self.width * gy
+    }
+
+    // D^-1 * weight * cell_dist in 1/m, the MLS approximation of the weight gradient.
+    // summed over the stencil as velocity * this^T it gives C = B * D^-1 from APIC.
+    pub(super) fn mls_weight_gradient(
+        &self,
+        grid: &Grid,
+        gx: usize,
+        gy: usize,
+        cell_dist: Vec2,
+    ) -> Vec2 {
+        match self.d_inverse {
+            Some(d_inverse) => cell_dist * (self.weight(gx, gy) * d_inverse),
+            None => self.spline_weight_gradient(grid, gx, gy),
+        }
+    }
+
+    // gradient in 1/m of the weight of stencil cell (gx, gy) at the particle.
+    // MLS-MPM uses the MLS approximation, the other transfer schemes differentiate the B-spline.
+    pub(super) fn weight_gradient(
+        &self,
+        grid: &Grid,
+        transfer: TransferScheme,
+        gx: usize,
+        gy: usize,
+        cell_dist: Vec2,
+    ) -> Vec2 {
+        if transfer == TransferScheme::MlsMpm {
+            self.mls_weight_gradient(grid, gx, gy, cell_dist)
+        } else {
+            self.spline_weight_gradient(grid, gx, gy)
+        }
+    }
+
+    fn spline_weight_gradient(&self, grid: &Grid, gx: usize, gy: usize) -> Vec2 {
+        Vec2::new(
+            self.weight_derivatives[gx].x * self.weights[gy].y,
+            self.weights[gx].x * self.weight_derivatives[gy].y,
+        ) * grid.inv_dx()
+    }
+}
+
+// fraction is the distance past the first cell center, in [0, 1)
+fn linear_interpolation_weights(fraction: Vec2) -> [Vec2; 2] {
+    [Vec2::ONE - fraction, fraction]
+}
+
+// cell_diff is the distance from the middle cell center, in [-0.5, 0.5)
+fn quadratic_interpolation_weights(cell_diff: Vec2) -> [Vec2; 3] {
+    [
+        Vec2::new(
+            0.5 * f32::powi(0.5 - cell_diff.x, 2),
+            0.5 * f32::powi(0.5 - cell_diff.y, 2),
+        ),
+        Vec2::new(
+            0.75 - f32::powi(cell_diff.x, 2),
+            0.75 - f32::powi(cell_diff.y, 2),
+        ),
+        Vec2::new(
+            0.5 * f32::powi(0.5 + cell_diff.x, 2),
+            0.5 * f32::powi(0.5 + cell_diff.y, 2),
+        ),
+    ]
+}
+
+fn quadratic_interpolation_weight_derivatives(cell_diff: Vec2) -> [Vec2; 3] {
+    [
+        cell_diff - Vec2::splat(0.5),
+        cell_diff * -2.0,
+        cell_diff + Vec2::splat(0.5),
+    ]
+}
+
+// fraction is the distance past the second cell center, in [0, 1)
+fn cubic_interpolation_weights(fraction: Vec2) -> [Vec2; 4] {
+    let rest = Vec2::ONE - fraction;
+    [
+        rest * rest * rest / 6.0,
+        fraction * fraction * fraction * 0.5 - fraction * fraction + Vec2::splat(2.0 / 3.0),
+        rest * rest * rest * 0.5 - rest * rest + Vec2::splat(2.0 / 3.0),
+        fraction * fraction * fraction / 6.0,
+    ]
+}
+
+fn cubic_interpolation_weight_derivatives(fraction: Vec2) -> [Vec2; 4] {
+    let rest = Vec2::ONE - fraction;
+    [
+        rest * rest * -0.5,
+        fraction * fraction * 1.5 - fraction * 2.0,
+        rest * rest * -1.5 + rest * 2.0,
+        fraction * fraction * 0.5,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [Kernel; 3] = [Kernel::Linear, Kernel::Quadratic, Kernel::Cubic];
+
+    // particles on, between and just short of cell centers and faces, in meters
+    fn positions() -> Vec<Vec2> {
+        vec![
+            Vec2::new(4.0, 4.0),
+            Vec2::new(4.25, 4.25),
+            Vec2::new(4.3, 5.71),
+            Vec2::new(5.49, 6.01),
+            Vec2::new(6.999, 3.5001),
+        ]
+    }
+
+    // runs check on the stencil of every kernel at every position
+    fn check_stencil(check: impl Fn(&Grid, Vec2, &Stencil)) {
+        let grid = Grid::new(Vec2::splat(16.), 0.5);
+        for kernel in KERNELS {
+            for position in positions() {
+                check(&grid, position, &kernel.stencil(&grid, position));
+            }
+        }
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        check_stencil(|_, position, stencil| {
+            let mut sum = 0.;
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    sum += stencil.weight(gx, gy);
+                }
+            }
+            assert!(
+                (sum - 1.).abs() < 1e-5,
+                "weights at {} sum to {}",
+                position,
+                sum
+            );
+        });
+    }
+
+    #[test]
+    fn weight_gradients_sum_to_zero() {
+        check_stencil(|grid, position, stencil| {
+            let mut sum = Vec2::ZERO;
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    let (x, y) = stencil.cell(gx, gy);
+                    let cell_dist = grid.node_position(x, y) - position;
+                    sum += stencil.weight_gradient(grid, TransferScheme::Pic, gx, gy, cell_dist);
+                }
+            }
+            assert!(
+                sum.length() < 1e-4,
+                "weight gradients at {} sum to {}",
+                position,
+                sum
+            );
+        });
+    }
+
+    #[test]
+    fn weighted_cell_distances_sum_to_zero() {
+        check_stencil(|grid, position, stencil| {
+            let mut sum = Vec2::ZERO;
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    let (x, y) = stencil.cell(gx, gy);
+                    sum += (grid.node_position(x, y) - position) * stencil.weight(gx, gy);
+                }
+            }
+            assert!(
+                sum.length() < 1e-5,
+                "weighted cell distances at {} sum to {}",
+                position,
+                sum
+            );
+        });
+    }
+}
@@ -0,0 +1,204 @@
+use bevy::asset::FileAssetIo;
+use bevy::math::{Mat2, Vec2};
+
+use crate::components::*;
+
+impl KinematicMotion {
+    pub(super) fn along_path(keyframes: Vec<Keyframe>) -> Self {
+        KinematicMotion {
+            keyframes,
+            spin: 0.,
+            pivot: Vec2::ZERO,
+            time: 0.,
+            offset: Vec2::ZERO,
+            rotation: 0.,
+            velocity: Vec2::ZERO,
+            angular_velocity: 0.,
+        }
+    }
+
+    pub(super) fn spinning(pivot: Vec2, spin: f32) -> Self {
+        KinematicMotion {
+            spin,
+            pivot,
+            ..KinematicMotion::along_path(vec![])
+        }
+    }
+
+    // advance along the path, which loops after the last keyframe
+    pub(super) fn advance(&mut self, dt: f32) {
+        self.time += dt;
+        let (offset, rotation, velocity, angular_velocity) = self.sample_path();
+        self.offset = offset;
+        self.rotation = rotation + self.spin * self.time;
+        self.velocity = velocity;
+        self.angular_velocity = angular_velocity + self.spin;
+    }
+
+    // offset, rotation and their rates of change at the current time
+    fn sample_path(&self) -> (Vec2, f32, Vec2, f32) {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return (Vec2::ZERO, 0., Vec2::ZERO, 0.),
+        };
+        if last.time <= 0. {
+            return (first.offset, first.rotation, Vec2::ZERO, 0.);
+        }
+
+        let time = self.time % last.time;
+        if time < first.time {
+            // waiting at the first keyframe
+            return (first.offset, first.rotation, Vec2::ZERO, 0.);
+        }
+        for segment in self.keyframes.windows(2) {
+            let (k0, k1) = (segment[0], segment[1]);
+            if time < k1.time {
+                let span = k1.time - k0.time;
+                let s = ((time - k0.time) / span).clamp(0., 1.);
+                return (
+                    k0.offset.lerp(k1.offset, s),
+                    k0.rotation + (k1.rotation - k0.rotation) * s,
+                    (k1.offset - k0.offset) / span,
+                    (k1.rotation - k0.rotation) / span,
+                );
+            }
+        }
+        (last.offset, last.rotation, Vec2::ZERO, 0.)
+    }
+
+    // where a world position was before the motion, to evaluate the collider shape there
+    pub(super) fn to_rest_position(&self, point: Vec2) -> Vec2 {
+        Mat2::from_angle(-self.rotation) * (point - self.pivot - self.offset) + self.pivot
+    }
+
+    // rotate a direction from the rest frame into the world
+    pub(super) fn rotate(&self, direction: Vec2) -> Vec2 {
+        Mat2::from_angle(self.rotation) * direction
+    }
+
+    pub(super) fn velocity_at(&self, point: Vec2) -> Vec2 {
+        self.velocity + (point - self.pivot - self.offset).perp() * self.angular_velocity
+    }
+
+    // world position of a point given in the rest frame
+    pub(super) fn to_world_position(&self, point: Vec2) -> Vec2 {
+        self.rotate(point - self.pivot) + self.pivot + self.offset
+    }
+}
+
+// keyframes as text, one per line: time offset_x offset_y rotation. '#' starts a comment.
+// times are in seconds and must increase.
+pub(super) fn parse_keyframes(text: &str) -> Result<Vec<Keyframe>, String> {
+    let mut keyframes: Vec<Keyframe> = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let values = line
+            .split_whitespace()
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+        if values.len() != 4 {
+            return Err(format!(
+                "line {}: expected time offset_x offset_y rotation",
+                line_number + 1
+            ));
+        }
+        let keyframe = Keyframe {
+            time: values[0],
+            offset: Vec2::new(values[1], values[2]),
+            rotation: values[3],
+        };
+        if let Some(previous) = keyframes.last() {
+            if keyframe.time <= previous.time {
+                return Err(format!("line {}: time must increase", line_number + 1));
+            }
+        }
+        keyframes.push(keyframe);
+    }
+    Ok(keyframes)
+}
+
+// keyframes from a path file in the assets folder, e.g. "paths/piston.path"
+pub(super) fn load_keyframes(path: &str) -> Result<Vec<Keyframe>, String> {
+    let full_path = FileAssetIo::get_base_path().join("assets").join(path);
+    let text = std::fs::read_to_string(&full_path)
+        .map_err(|e| format!("{}: {}", full_path.display(), e))?;
+    parse_keyframes(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keyframes_skipping_comments_and_blank_lines() {
+        let keyframes =
+            parse_keyframes("# header\n\n0 0 0 0\n  1.5 -24 2 0.5  # moving\n").unwrap();
+        assert_eq!(keyframes.len(), 2);
+        assert_eq!(keyframes[1].time, 1.5);
+        assert_eq!(keyframes[1].offset, Vec2::new(-24., 2.));
+        assert_eq!(keyframes[1].rotation, 0.5);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(
+            parse_keyframes("0 0 0 0\n1 x 0 0").unwrap_err(),
+            "line 2: invalid float literal"
+        );
+        assert_eq!(
+            parse_keyframes("0 0 0").unwrap_err(),
+            "line 1: expected time offset_x offset_y rotation"
+        );
+        assert_eq!(
+            parse_keyframes("0 0 0 0 0").unwrap_err(),
+            "line 1: expected time offset_x offset_y rotation"
+        );
+        assert_eq!(
+            parse_keyframes("1 0 0 0\n# comment\n1 0 0 0").unwrap_err(),
+            "line 3: time must increase"
+        );
+    }
+
+    #[test]
+    fn path_loops_after_the_last_keyframe() {
+        let mut motion =
+            KinematicMotion::along_path(parse_keyframes("0 0 0 0\n2 10 0 1\n4 0 0 0").unwrap());
+        motion.advance(1.);
+        assert_eq!(motion.offset, Vec2::new(5., 0.));
+        assert_eq!(motion.velocity, Vec2::new(5., 0.));
+
+        // 5s is 1s into the second loop
+        motion.advance(4.);
+        assert_eq!(motion.offset, Vec2::new(5., 0.));
+        assert_eq!(motion.rotation, 0.5);
+        assert_eq!(motion.angular_velocity, 0.5);
+
+        // and 3s on the way back
+        motion.advance(2.);
+        assert_eq!(motion.offset, Vec2::new(5., 0.));
+        assert_eq!(motion.velocity, Vec2::new(-5., 0.));
+    }
+
+    #[test]
+    fn path_waits_at_the_first_keyframe() {
+        let mut motion =
+            KinematicMotion::along_path(parse_keyframes("1 4 0 0\n2 10 0 1\n4 0 0 0").unwrap());
+        motion.advance(0.5);
+        assert_eq!(motion.offset, Vec2::new(4., 0.));
+        assert_eq!(motion.velocity, Vec2::ZERO);
+        assert_eq!(motion.angular_velocity, 0.);
+
+        motion.advance(1.);
+        assert_eq!(motion.offset, Vec2::new(7., 0.));
+        assert_eq!(motion.velocity, Vec2::new(6., 0.));
+    }
+
+    #[test]
+    fn loads_the_piston_path() {
+        assert_eq!(load_keyframes("paths/piston.path").unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,153 @@
+use bevy::math::{Mat2, Vec2};
+
+// polar decomposition F = R * S of a 2x2 matrix, with R a rotation and S symmetric.
+pub(super) fn polar_decomposition(f: Mat2) -> (Mat2, Mat2) {
+    let x = f.x_axis.x + f.y_axis.y;
+    let y = f.x_axis.y - f.y_axis.x;
+    let norm = f32::sqrt(x * x + y * y);
+    if norm < f32::EPSILON {
+        return (Mat2::IDENTITY, f);
+    }
+    let c = x / norm;
+    let s = y / norm;
+    let r = Mat2::from_cols(Vec2::new(c, s), Vec2::new(-s, c));
+    (r, r.transpose().mul_mat2(&f))
+}
+
+// cofactor matrix J F^-T of a 2x2 matrix, linear in F and defined even when F is singular.
+pub(super) fn cofactor(f: Mat2) -> Mat2 {
+    Mat2::from_cols(
+        Vec2::new(f.y_axis.y, -f.y_axis.x),
+        Vec2::new(-f.x_axis.y, f.x_axis.x),
+    )
+}
+
+// singular value decomposition F = U * diag(sigma) * V^T of a 2x2 matrix.
+// singular values are sorted largest first, U and V are rotations so the smaller one can be negative.
+// ported from the 2D SVD in taichi (https://github.com/taichi-dev/taichi/blob/master/python/taichi/_funcs.py)
+pub(super) fn svd(f: Mat2) -> (Mat2, Vec2, Mat2) {
+    let (r, s) = polar_decomposition(f);
+    let s00 = s.x_axis.x;
+    let s01 = s.y_axis.x;
+    let s11 = s.y_axis.y;
+
+    let (c, sn, mut sig_0, mut sig_1) = if s01.abs() < 1e-5 {
+        (1.0, 0.0, s00, s11)
+    } else {
+        let tao = 0.5 * (s00 - s11);
+        let w = f32::sqrt(tao * tao + s01 * s01);
+        let t = if tao > 0.0 {
+            s01 / (tao + w)
+        } else {
+            s01 / (tao - w)
+        };
+        let c = 1.0 / f32::sqrt(t * t + 1.0);
+        let sn = -t * c;
+        (
+            c,
+            sn,
+            c * c * s00 - 2.0 * c * sn * s01 + sn * sn * s11,
+            sn * sn * s00 + 2.0 * c * sn * s01 + c * c * s11,
+        )
+    };
+
+    let v = if sig_0 < sig_1 {
+        std::mem::swap(&mut sig_0, &mut sig_1);
+        Mat2::from_cols(Vec2::new(-sn, -c), Vec2::new(c, -sn))
+    } else {
+        Mat2::from_cols(Vec2::new(c, -sn), Vec2::new(sn, c))
+    };
+
+    (r.mul_mat2(&v), Vec2::new(sig_0, sig_1), v)
+}
+
+// eigen decomposition of a symmetric 2x2 matrix into eigenvalues (largest first) and eigenvectors as columns.
+pub(super) fn symmetric_eigen(m: Mat2) -> (Vec2, Mat2) {
+    let mean = 0.5 * (m.x_axis.x + m.y_axis.y);
+    let half_diff = 0.5 * (m.x_axis.x - m.y_axis.y);
+    let off_diagonal = m.y_axis.x;
+    let radius = f32::sqrt(half_diff * half_diff + off_diagonal * off_diagonal);
+    let angle = 0.5 * f32::atan2(off_diagonal, half_diff);
+    let (sin, cos) = angle.sin_cos();
+    (
+        Vec2::new(mean + radius, mean - radius),
+        Mat2::from_cols(Vec2::new(cos, sin), Vec2::new(-sin, cos)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // stretched, sheared, rotated, inverted and already diagonal deformation gradients
+    fn matrices() -> Vec<Mat2> {
+        vec![
+            Mat2::IDENTITY,
+            Mat2::from_diagonal(Vec2::new(1.5, 0.7)),
+            Mat2::from_cols(Vec2::new(1.2, 0.3), Vec2::new(-0.4, 0.9)),
+            Mat2::from_cols(Vec2::new(0.8, -0.6), Vec2::new(0.6, 0.8)),
+            Mat2::from_cols(Vec2::new(0.5, 1.1), Vec2::new(1.3, 0.2)),
+            Mat2::from_cols(Vec2::new(1.0, 0.0), Vec2::new(2.0, 1.0)),
+        ]
+    }
+
+    fn assert_close(a: Mat2, b: Mat2, what: &str) {
+        assert!(
+            (a - b).to_cols_array().iter().all(|d| d.abs() < 1e-4),
+            "{}: {} != {}",
+            what,
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn polar_decomposition_splits_into_rotation_and_symmetric() {
+        for f in matrices() {
+            let (r, s) = polar_decomposition(f);
+            assert_close(r.mul_mat2(&s), f, "R * S");
+            assert_close(r.transpose().mul_mat2(&r), Mat2::IDENTITY, "R^T * R");
+            assert!((r.determinant() - 1.).abs() < 1e-4, "det R of {}", f);
+            assert_close(s, s.transpose(), "S symmetric");
+        }
+    }
+
+    #[test]
+    fn svd_reconstructs_the_matrix() {
+        for f in matrices() {
+            let (u, sigma, v) = svd(f);
+            assert_close(
+                u.mul_mat2(&Mat2::from_diagonal(sigma))
+                    .mul_mat2(&v.transpose()),
+                f,
+                "U * sigma * V^T",
+            );
+            assert_close(u.transpose().mul_mat2(&u), Mat2::IDENTITY, "U^T * U");
+            assert_close(v.transpose().mul_mat2(&v), Mat2::IDENTITY, "V^T * V");
+            assert!(sigma.x >= sigma.y, "singular values of {} unsorted", f);
+            assert!(sigma.x >= 0., "largest singular value of {} negative", f);
+        }
+    }
+
+    #[test]
+    fn symmetric_eigen_diagonalizes_the_matrix() {
+        for f in matrices() {
+            // symmetric stress-like matrices, some with repeated eigenvalues
+            let m = f.transpose().mul_mat2(&f) - Mat2::IDENTITY;
+            let (values, vectors) = symmetric_eigen(m);
+            assert!(values.x >= values.y, "eigenvalues of {} unsorted", m);
+            assert_close(
+                vectors.transpose().mul_mat2(&vectors),
+                Mat2::IDENTITY,
+                "eigenvectors orthonormal",
+            );
+            assert_close(
+                vectors
+                    .mul_mat2(&Mat2::from_diagonal(values))
+                    .mul_mat2(&vectors.transpose()),
+                m,
+                "Q * lambda * Q^T",
+            );
+        }
+    }
+}
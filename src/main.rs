@@ -1,5 +1,3 @@
-#![allow(clippy::too_many_arguments)]
-
 use bevy::diagnostic::{
     EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin,
 };
@@ -8,23 +6,41 @@ use bevy::window::WindowMode::BorderlessFullscreen;
 use bevy_egui::EguiPlugin;
 
 use camera::*;
+use components::*;
+use constitutive_model_plugin::*;
 use spawners::*;
+use substeps::*;
 
 use crate::defaults::*;
 
+mod boundaries;
 mod camera;
 mod components;
+mod constitutive_model_plugin;
 mod defaults;
 mod expire_old;
 mod grid;
+mod implicit;
 mod inputs;
+mod kernel;
+mod kinematic;
+mod linalg;
 mod particle_sprites;
+mod plasticity;
+mod sdf;
 mod spawners;
+mod step_adaptive_dt;
 mod step_g2p;
+mod step_heat;
+mod step_move_colliders;
 mod step_p2g;
+mod step_phase_change;
+mod step_rigid_bodies;
 mod step_update_cells;
+mod step_update_damage;
 mod step_update_deformations;
 mod step_update_grid;
+mod substeps;
 mod world;
 
 fn main() {
@@ -37,72 +53,129 @@ fn main() {
             mode: BorderlessFullscreen,
             ..Default::default()
         })
-        .insert_resource(grid::Grid::new(DEFAULT_GRID_WIDTH))
+        .insert_resource(grid::Grid::new(
+            Vec2::new(DEFAULT_DOMAIN_WIDTH, DEFAULT_DOMAIN_HEIGHT),
+            DEFAULT_DX,
+        ))
         .insert_resource(world::WorldState::default())
+        .init_resource::<implicit::ImplicitParticles>()
         .add_plugins(DefaultPlugins)
         .add_plugin(LogDiagnosticsPlugin::default())
         .add_plugin(EguiPlugin)
-        .add_plugin(EntityCountDiagnosticsPlugin::default())
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_plugin(EntityCountDiagnosticsPlugin)
+        .add_plugin(FrameTimeDiagnosticsPlugin)
         .add_startup_system(setup_camera)
         .add_startup_system(create_initial_spawners)
+        .add_startup_system(step_adaptive_dt::setup_time_step_diagnostic)
         //.add_system(
         //    set_zoom_from_window_size
         //        .label("set_zoom_from_window_size")
         //        .before("handle_inputs"),
         //)
         .add_system(bevy::window::close_on_esc)
-        .add_system(
-            inputs::handle_inputs
-                .label("handle_inputs")
-                .before("tick_spawners"),
-        )
-        .add_system(
-            spawners::tick_spawners
-                .label("tick_spawners")
-                .before("reset_grid"),
-        )
-        .add_system(grid::reset_grid.label("reset_grid").before("update_cells"))
-        .add_system(
+        .add_system(inputs::handle_inputs.label("handle_inputs"))
+        // the simulation runs at a fixed dt, as many times per frame as it takes to keep up
+        .add_stage_before(
+            CoreStage::Update,
+            SIMULATION_STAGE,
+            SystemStage::parallel().with_run_criteria(run_substeps),
+        )
+        .add_plugin(ConstitutiveModelPlugin::<NewtonianFluidModel>::default())
+        .add_plugin(ConstitutiveModelPlugin::<NonNewtonianFluidModel>::default())
+        .add_plugin(ConstitutiveModelPlugin::<ViscoelasticModel>::default())
+        .add_plugin(ConstitutiveModelPlugin::<NeoHookeanHyperElasticModel>::default())
+        .add_plugin(ConstitutiveModelPlugin::<FixedCorotatedModel>::default())
+        .add_plugin(ConstitutiveModelPlugin::<DruckerPragerPlasticModel>::default())
+        .add_plugin(ConstitutiveModelPlugin::<SnowModel>::default())
+        .add_system_to_stage(SIMULATION_STAGE, step_adaptive_dt::record_time_step)
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            grid::reset_grid
+                .label("reset_grid")
+                .before("update_cells")
+                .before("p2g_heat"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
             step_update_cells::update_cells
                 .label("update_cells")
                 .before("apply_update_cell_computations"),
         )
-        .add_system(
+        .add_system_to_stage(
+            SIMULATION_STAGE,
             step_update_cells::apply_update_cell_computations
                 .label("apply_update_cell_computations")
-                .before("p2g_f")
-                .before("p2g_s"),
+                .before("p2g"),
         )
-        .add_system(
-            step_p2g::particles_to_grid_fluids
-                .label("p2g_f")
-                .before("update_grid"),
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_heat::particles_to_grid_heat
+                .label("p2g_heat")
+                .before("apply_heat_contributions"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_heat::apply_heat_contributions
+                .label("apply_heat_contributions")
+                .before("conduct_heat"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_heat::conduct_heat
+                .label("conduct_heat")
+                .before("g2p_heat"),
         )
-        .add_system(
-            step_p2g::particles_to_grid_solids
-                .label("p2g_s")
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_heat::grid_to_particles_heat
+                .label("g2p_heat")
+                .before("g2p")
+                .before("update_phases"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_phase_change::update_phases
+                .label("update_phases")
+                .before("delete_outflow_particles")
+                .before("delete_old_entities"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_move_colliders::move_colliders
+                .label("move_colliders")
                 .before("update_grid"),
         )
-        .add_system(
+        .add_system_to_stage(
+            SIMULATION_STAGE,
             step_update_grid::update_grid
                 .label("update_grid")
-                .before("g2p"),
+                .before("g2p")
+                .before("integrate_rigid_bodies"),
         )
-        .add_system(
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_rigid_bodies::integrate_rigid_bodies.label("integrate_rigid_bodies"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
             step_g2p::grid_to_particles
                 .label("g2p")
-                .before("update_deformation_gradients"),
+                .before("update_deformation_gradients")
+                .before("delete_outflow_particles"),
         )
-        .add_system(
-            step_update_deformations::update_deformation_gradients
-                .label("update_deformation_gradients")
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            step_update_damage::update_damage
+                .label("update_damage")
                 .before("delete_old_entities"),
         )
-        .add_system(
-            expire_old::delete_old_entities
-                .label("delete_old_entities")
-                .before("update_sprites"),
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            expire_old::delete_outflow_particles.label("delete_outflow_particles"),
+        )
+        .add_system_to_stage(
+            SIMULATION_STAGE,
+            expire_old::delete_old_entities.label("delete_old_entities"),
         )
         .add_system(particle_sprites::update_sprites.label("update_sprites"))
         .run();
@@ -2,13 +2,29 @@ use bevy::prelude::*;
 
 use crate::components::*;
 use crate::defaults::*;
+use crate::grid::Grid;
 
-pub(super) fn update_sprites(mut particles: Query<(&mut Transform, &Position), With<ParticleTag>>) {
+// particles whose damage changed since the sprites were last updated
+type NewlyDamaged = (With<ParticleTag>, Changed<Damage>);
+
+// sprites are drawn in grid cells rather than meters, so sprite sizes don't depend on dx.
+pub(super) fn update_sprites(
+    grid: Res<Grid>,
+    mut particles: Query<(&mut Transform, &Position), With<ParticleTag>>,
+    mut particles_damaged: Query<(&mut Sprite, &Damage), NewlyDamaged>,
+) {
     // todo adjust size relative to mass, min/max size determined by grid+window sizes
     // todo color based on velocity. (maybe acceleration?)
     // todo color based on constitutive model. (or initial texture.)
+    let inv_dx = grid.inv_dx();
     particles.par_for_each_mut(PAR_BATCH_SIZE, |(mut transform, position)| {
-        transform.translation.x = position.0.x;
-        transform.translation.y = position.0.y;
+        transform.translation.x = position.0.x * inv_dx;
+        transform.translation.y = position.0.y * inv_dx;
+    });
+
+    // darken and redden solids as they break
+    particles_damaged.par_for_each_mut(PAR_BATCH_SIZE, |(mut sprite, damage)| {
+        let intact = 1. - 0.6 * damage.0;
+        sprite.color = Color::rgb(1., intact, intact);
     });
 }
@@ -0,0 +1,270 @@
+use bevy::math::{Mat2, Vec2};
+
+use crate::components::*;
+use crate::linalg::*;
+
+// von Mises return mapping for hyperelastic solids with plasticity enabled.
+// the deviatoric log strain is scaled back onto the yield surface so the material keeps its damaged state.
+impl NeoHookeanHyperElasticModel {
+    pub(super) fn return_map(&mut self) {
+        let mut plasticity = match self.plasticity {
+            Some(plasticity) => plasticity,
+            None => return,
+        };
+
+        let (u, sigma, v) = svd(self.deformation_gradient);
+        let log_strain = Vec2::new(sigma.x.abs().max(1e-4).ln(), sigma.y.abs().max(1e-4).ln());
+        let trace = log_strain.x + log_strain.y;
+        let deviatoric = log_strain - Vec2::splat(trace / 2.);
+        let deviatoric_norm = deviatoric.length();
+
+        let yield_stress =
+            plasticity.yield_stress + plasticity.hardening * plasticity.plastic_strain;
+        let excess = 2. * self.elastic_mu * deviatoric_norm - f32::sqrt(2. / 3.) * yield_stress;
+        if excess <= 0. || deviatoric_norm < f32::EPSILON {
+            return;
+        }
+
+        // hardening raises the yield stress as the material flows, which limits how far it flows
+        let delta_gamma = excess / (2. * self.elastic_mu + 2. / 3. * plasticity.hardening);
+        let log_strain_projected = log_strain - deviatoric * (delta_gamma / deviatoric_norm);
+        self.deformation_gradient = u
+            .mul_mat2(&Mat2::from_diagonal(Vec2::new(
+                log_strain_projected.x.exp(),
+                log_strain_projected.y.exp() * sigma.y.signum(),
+            )))
+            .mul_mat2(&v.transpose());
+
+        plasticity.plastic_strain += delta_gamma;
+        self.plasticity = Some(plasticity);
+    }
+}
+
+// Drucker-Prager return mapping from Klar et al. 2016, "Drucker-Prager Elastoplasticity for Sand Animation".
+// projects the elastic deformation gradient back onto the yield surface in log-strain space
+// and accumulates the plastic deformation into the hardening state.
+impl DruckerPragerPlasticModel {
+    pub(super) fn return_map(&mut self) {
+        let (u, sigma, v) = svd(self.deformation_gradient);
+
+        // shift by cohesion so the tip of the yield cone allows some tension
+        let cohesion_shift = Vec2::splat(self.cohesion / 2.);
+        let log_strain =
+            Vec2::new(sigma.x.abs().max(1e-4).ln(), sigma.y.abs().max(1e-4).ln()) - cohesion_shift;
+        let trace = log_strain.x + log_strain.y;
+        let deviatoric = log_strain - Vec2::splat(trace / 2.);
+        let deviatoric_norm = deviatoric.length();
+
+        let (log_strain_projected, plastic_change) = if trace >= 0. {
+            // case II: material is being pulled apart, project to the tip of the cone
+            (Vec2::ZERO, log_strain.length())
+        } else if deviatoric_norm < f32::EPSILON {
+            // pure compression is always inside the cone
+            (log_strain, 0.)
+        } else {
+            let delta_gamma = deviatoric_norm
+                + (2. * self.elastic_lambda + 2. * self.elastic_mu) / (2. * self.elastic_mu)
+                    * trace
+                    * self.alpha();
+            if delta_gamma <= 0. {
+                // case I: inside the yield surface, elastic only
+                (log_strain, 0.)
+            } else {
+                // case III: project onto the side of the cone
+                (
+                    log_strain - deviatoric * (delta_gamma / deviatoric_norm),
+                    delta_gamma,
+                )
+            }
+        };
+
+        if plastic_change > 0. {
+            let sigma_projected = log_strain_projected + cohesion_shift;
+            self.deformation_gradient = u
+                .mul_mat2(&Mat2::from_diagonal(Vec2::new(
+                    sigma_projected.x.exp(),
+                    sigma_projected.y.exp(),
+                )))
+                .mul_mat2(&v.transpose());
+            self.hardening_state += plastic_change;
+        }
+    }
+}
+
+// snow plasticity from Stomakhin et al. 2013. singular values of the elastic deformation gradient
+// are clamped to the critical compression/stretch and the excess is moved into the plastic part.
+impl SnowModel {
+    pub(super) fn return_map(&mut self) {
+        let deformation_total = self
+            .elastic_deformation_gradient
+            .mul_mat2(&self.plastic_deformation_gradient);
+
+        let (u, sigma, v) = svd(self.elastic_deformation_gradient);
+        let sigma_clamped = sigma.clamp(
+            Vec2::splat(1. - self.critical_compression),
+            Vec2::splat(1. + self.critical_stretch),
+        );
+
+        self.elastic_deformation_gradient = u
+            .mul_mat2(&Mat2::from_diagonal(sigma_clamped))
+            .mul_mat2(&v.transpose());
+        self.plastic_deformation_gradient = v
+            .mul_mat2(&Mat2::from_diagonal(Vec2::ONE / sigma_clamped))
+            .mul_mat2(&u.transpose())
+            .mul_mat2(&deformation_total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn return_map(mut model: DruckerPragerPlasticModel) -> DruckerPragerPlasticModel {
+        model.return_map();
+        model
+    }
+
+    // distance of the log strain outside the yield cone, <= 0 inside
+    fn yield_function(model: &DruckerPragerPlasticModel) -> f32 {
+        let (_, sigma, _) = svd(model.deformation_gradient);
+        let log_strain = Vec2::new(sigma.x.ln(), sigma.y.ln()) - Vec2::splat(model.cohesion / 2.);
+        let trace = log_strain.x + log_strain.y;
+        let deviatoric = log_strain - Vec2::splat(trace / 2.);
+        deviatoric.length()
+            + (model.elastic_lambda + model.elastic_mu) / model.elastic_mu * trace * model.alpha()
+    }
+
+    #[test]
+    fn sheared_sand_projects_onto_the_yield_cone() {
+        let sheared = DruckerPragerPlasticModel {
+            // compressed a little and stretched sideways a lot
+            deformation_gradient: Mat2::from_diagonal(Vec2::new(1.2, 0.75)),
+            ..sand_properties()
+        };
+        assert!(yield_function(&sheared) > 0.);
+
+        let projected = return_map(sheared);
+        assert!(projected.hardening_state > 0.);
+
+        // on the surface of the cone it was projected onto, before hardening widened it
+        let distance = yield_function(&DruckerPragerPlasticModel {
+            hardening_state: sheared.hardening_state,
+            ..projected
+        });
+        assert!(
+            distance.abs() < 1e-4,
+            "projected state is {} from the cone",
+            distance
+        );
+    }
+
+    #[test]
+    fn compressed_sand_inside_the_cone_is_unchanged() {
+        let compressed = DruckerPragerPlasticModel {
+            deformation_gradient: Mat2::from_diagonal(Vec2::new(0.98, 0.97)),
+            ..sand_properties()
+        };
+        assert!(yield_function(&compressed) < 0.);
+
+        let projected = return_map(compressed);
+        assert_eq!(
+            projected.deformation_gradient,
+            compressed.deformation_gradient
+        );
+        assert_eq!(projected.hardening_state, 0.);
+    }
+
+    #[test]
+    fn stretched_sand_projects_to_the_tip_of_the_cone() {
+        let stretched = DruckerPragerPlasticModel {
+            deformation_gradient: Mat2::from_diagonal(Vec2::new(1.1, 1.05)),
+            ..sand_properties()
+        };
+
+        let projected = return_map(stretched);
+        let (_, sigma, _) = svd(projected.deformation_gradient);
+        assert!((sigma - Vec2::ONE).length() < 1e-4, "sigma is {}", sigma);
+    }
+
+    #[test]
+    fn compressed_snow_clamps_and_hardens() {
+        let deformation = Mat2::from_diagonal(Vec2::new(0.9, 1.002));
+        let mut compressed = SnowModel {
+            elastic_deformation_gradient: deformation,
+            ..snow_properties()
+        };
+        compressed.return_map();
+
+        // the elastic part is clamped to the critical compression, the rest moves into the plastic part
+        let (_, sigma, _) = svd(compressed.elastic_deformation_gradient);
+        let sigma_min = sigma.x.min(sigma.y);
+        assert!(
+            (sigma_min - (1. - compressed.critical_compression)).abs() < 1e-4,
+            "elastic singular value is {}",
+            sigma_min
+        );
+        let total = compressed
+            .elastic_deformation_gradient
+            .mul_mat2(&compressed.plastic_deformation_gradient);
+        assert!(
+            total.abs_diff_eq(deformation, 1e-4),
+            "total deformation changed to {}",
+            total
+        );
+
+        // packed snow gets stiffer
+        assert!(compressed.plastic_deformation_gradient.determinant() < 1.);
+        let (elastic_lambda, elastic_mu) = compressed.hardened_lame_parameters();
+        assert!(elastic_lambda > compressed.elastic_lambda);
+        assert!(elastic_mu > compressed.elastic_mu);
+    }
+
+    #[test]
+    fn snow_within_the_critical_range_stays_elastic() {
+        let deformation = Mat2::from_diagonal(Vec2::new(0.99, 1.005));
+        let mut snow = SnowModel {
+            elastic_deformation_gradient: deformation,
+            ..snow_properties()
+        };
+        snow.return_map();
+
+        assert!(snow
+            .elastic_deformation_gradient
+            .abs_diff_eq(deformation, 1e-5));
+        assert!(snow
+            .plastic_deformation_gradient
+            .abs_diff_eq(Mat2::IDENTITY, 1e-5));
+        let (elastic_lambda, _) = snow.hardened_lame_parameters();
+        assert!(
+            (elastic_lambda / snow.elastic_lambda - 1.).abs() < 1e-4,
+            "lame parameter scaled by {}",
+            elastic_lambda / snow.elastic_lambda
+        );
+    }
+
+    fn plastic_strain_after_shear(hardening: f32) -> f32 {
+        let mut sheared = NeoHookeanHyperElasticModel {
+            deformation_gradient: Mat2::from_diagonal(Vec2::new(1.05, 0.96)),
+            ..steel_properties()
+        };
+        sheared.plasticity = sheared.plasticity.map(|plasticity| VonMisesPlasticity {
+            hardening,
+            ..plasticity
+        });
+        sheared.return_map();
+        sheared.plasticity.unwrap().plastic_strain
+    }
+
+    #[test]
+    fn hardening_limits_von_mises_plastic_flow() {
+        let perfectly_plastic = plastic_strain_after_shear(0.);
+        let hardened = plastic_strain_after_shear(steel_properties().elastic_mu);
+        assert!(perfectly_plastic > 0.);
+        assert!(
+            hardened < perfectly_plastic,
+            "hardened flow {} is not below perfectly plastic flow {}",
+            hardened,
+            perfectly_plastic
+        );
+    }
+}
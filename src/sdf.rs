@@ -0,0 +1,184 @@
+use bevy::math::{Mat2, Vec2};
+
+use crate::components::*;
+
+// signed distance functions, see https://iquilezles.org/articles/distfunctions2d/
+impl ColliderShape {
+    pub(super) fn distance(&self, p: Vec2) -> f32 {
+        match self {
+            ColliderShape::Circle { center, radius } => (p - *center).length() - radius,
+            ColliderShape::Box {
+                center,
+                half_extents,
+                rotation,
+            } => {
+                let local = Mat2::from_angle(*rotation).transpose() * (p - *center);
+                let q = local.abs() - *half_extents;
+                q.max(Vec2::ZERO).length() + q.x.max(q.y).min(0.)
+            }
+            ColliderShape::Capsule { a, b, radius } => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = (pa.dot(ba) / ba.dot(ba)).clamp(0., 1.);
+                (pa - ba * h).length() - radius
+            }
+            ColliderShape::Polygon(vertices) => polygon_distance(vertices, p),
+            ColliderShape::Union(a, b) => a.distance(p).min(b.distance(p)),
+            ColliderShape::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+        }
+    }
+
+    // shapes the distance functions can't evaluate: polygons with fewer than three vertices
+    // or the same vertex twice in a row, and capsules with both ends at the same point
+    pub(super) fn validate(&self) -> Result<(), String> {
+        match self {
+            ColliderShape::Capsule { a, b, .. } if a == b => {
+                Err(format!("capsule ends are both at {}", a))
+            }
+            ColliderShape::Polygon(vertices) if vertices.len() < 3 => Err(format!(
+                "polygon has {} vertices, needs at least 3",
+                vertices.len()
+            )),
+            ColliderShape::Polygon(vertices) => {
+                match (0..vertices.len())
+                    .find(|i| vertices[*i] == vertices[(i + 1) % vertices.len()])
+                {
+                    Some(i) => Err(format!("polygon vertex {} is repeated", i)),
+                    None => Ok(()),
+                }
+            }
+            ColliderShape::Union(a, b) | ColliderShape::Difference(a, b) => {
+                a.validate()?;
+                b.validate()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // outward surface normal from the distance gradient, sampled a quarter cell of size dx around p
+    pub(super) fn normal(&self, p: Vec2, dx: f32) -> Vec2 {
+        let eps = 0.25 * dx;
+        Vec2::new(
+            self.distance(p + Vec2::new(eps, 0.)) - self.distance(p - Vec2::new(eps, 0.)),
+            self.distance(p + Vec2::new(0., eps)) - self.distance(p - Vec2::new(0., eps)),
+        )
+        .normalize_or_zero()
+    }
+}
+
+fn polygon_distance(vertices: &[Vec2], p: Vec2) -> f32 {
+    let mut distance_squared = (p - vertices[0]).length_squared();
+    let mut sign = 1.;
+    let mut j = vertices.len() - 1;
+    for (i, vi) in vertices.iter().enumerate() {
+        let vj = vertices[j];
+        let edge = vj - *vi;
+        let w = p - *vi;
+        let closest = w - edge * (w.dot(edge) / edge.dot(edge)).clamp(0., 1.);
+        distance_squared = distance_squared.min(closest.length_squared());
+
+        // winding number test for the inside
+        let crossings = [p.y >= vi.y, p.y < vj.y, edge.x * w.y > edge.y * w.x];
+        if crossings.iter().all(|c| *c) || crossings.iter().all(|c| !*c) {
+            sign = -sign;
+        }
+        j = i;
+    }
+    sign * distance_squared.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_distance(shape: &ColliderShape, p: Vec2, expected: f32) {
+        let distance = shape.distance(p);
+        assert!(
+            (distance - expected).abs() < 1e-5,
+            "distance at {} is {}, expected {}",
+            p,
+            distance,
+            expected
+        );
+    }
+
+    #[test]
+    fn polygon_distance_is_signed_by_the_inside() {
+        let square = ColliderShape::Polygon(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(4., 0.),
+            Vec2::new(4., 4.),
+            Vec2::new(0., 4.),
+        ]);
+        assert_distance(&square, Vec2::new(2., 2.), -2.);
+        assert_distance(&square, Vec2::new(1., 2.), -1.);
+        assert_distance(&square, Vec2::new(2., 0.), 0.);
+        assert_distance(&square, Vec2::new(6., 2.), 2.);
+        // outside a corner the closest point is the vertex
+        assert_distance(&square, Vec2::new(7., 8.), 5.);
+
+        // the winding doesn't matter
+        let triangle = ColliderShape::Polygon(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 3.),
+            Vec2::new(3., 0.),
+        ]);
+        assert_distance(&triangle, Vec2::new(0.5, 0.5), -0.5);
+        assert_distance(&triangle, Vec2::new(-1., 1.), 1.);
+    }
+
+    #[test]
+    fn capsule_distance_is_from_the_segment() {
+        let capsule = ColliderShape::Capsule {
+            a: Vec2::new(0., 0.),
+            b: Vec2::new(4., 0.),
+            radius: 1.,
+        };
+        assert_distance(&capsule, Vec2::new(2., 0.), -1.);
+        assert_distance(&capsule, Vec2::new(2., 3.), 2.);
+        // past the ends the distance is from the end points
+        assert_distance(&capsule, Vec2::new(-3., 0.), 2.);
+        assert_distance(&capsule, Vec2::new(7., 4.), 4.);
+        assert_eq!(capsule.normal(Vec2::new(2., 3.), 1.), Vec2::Y);
+    }
+
+    #[test]
+    fn empty_shapes_are_rejected() {
+        let square = || ColliderShape::Box {
+            center: Vec2::ZERO,
+            half_extents: Vec2::ONE,
+            rotation: 0.,
+        };
+        let invalid = [
+            ColliderShape::Polygon(vec![]),
+            ColliderShape::Polygon(vec![Vec2::ZERO, Vec2::X]),
+            ColliderShape::Polygon(vec![Vec2::ZERO, Vec2::X, Vec2::X, Vec2::Y]),
+            ColliderShape::Polygon(vec![Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ZERO]),
+            ColliderShape::Capsule {
+                a: Vec2::ONE,
+                b: Vec2::ONE,
+                radius: 1.,
+            },
+            ColliderShape::Difference(Box::new(square()), Box::new(ColliderShape::Polygon(vec![]))),
+        ];
+        for shape in invalid.iter() {
+            assert!(shape.validate().is_err());
+        }
+
+        let valid = [
+            square(),
+            ColliderShape::Polygon(vec![Vec2::ZERO, Vec2::X, Vec2::Y]),
+            ColliderShape::Union(
+                Box::new(square()),
+                Box::new(ColliderShape::Capsule {
+                    a: Vec2::ZERO,
+                    b: Vec2::X,
+                    radius: 1.,
+                }),
+            ),
+        ];
+        for shape in valid.iter() {
+            assert!(shape.validate().is_ok());
+        }
+    }
+}
@@ -1,14 +1,14 @@
 use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use rand::Rng;
 
 use super::components::*;
+use super::defaults::*;
 use super::grid::*;
+use super::kernel::MAX_STENCIL_SIZE;
+use super::kinematic::*;
 use super::world::*;
 
-const LIQUID_PARTICLE_MASS: f32 = 1.;
-const WOOD_PARTICLE_MASS: f32 = 1.;
-const STEEL_PARTICLE_MASS: f32 = 1.5;
-
 // Tags particle spawner entities
 #[derive(Component)]
 pub(super) struct ParticleSpawnerTag;
@@ -29,6 +29,8 @@ pub(super) enum SpawnerPattern {
 pub(super) struct ParticleSpawnerInfo {
     pub(super) created_at: usize,
     pub(super) pattern: SpawnerPattern,
+    // particle spacing, patterns lay their particles out this many to a cell
+    pub(super) particles_per_cell: f32,
     pub(super) spawn_frequency: usize,
     pub(super) max_particles: usize,
     pub(super) particle_duration: usize,
@@ -36,32 +38,41 @@ pub(super) struct ParticleSpawnerInfo {
     pub(super) particle_velocity: Vec2,
     pub(super) particle_velocity_random_vec_a: Vec2,
     pub(super) particle_velocity_random_vec_b: Vec2,
-    pub(super) particle_mass: f32,
+    // material density in kg/m^3, particle mass follows from it and the particle spacing
+    pub(super) particle_density: f32,
+    pub(super) particle_temperature: f32,
+    pub(super) particle_thermal_properties: ThermalProperties,
+    // materials in different contact fields slide against each other instead of sticking
+    pub(super) particle_contact_field: usize,
+    pub(super) particle_phase_change: Option<PhaseChange>,
 }
 
 pub(super) fn create_initial_spawners(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
     grid: Res<Grid>,
 ) {
+    let domain = grid.domain_size();
+
     // shoot arrows to the right
-    // young's modulus and shear modulus of steel.
-    // 180 Gpa young's
-    // 78Gpa shear
     commands.spawn_bundle((
-        // todo density option to spawners
-        // todo calculate correct particle mass from material density and particle density
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Triangle,
+            particles_per_cell: 16.,
             spawn_frequency: 800,
             max_particles: 200000,
             particle_duration: 40000,
-            particle_origin: Vec2::new(1.1 * grid.width as f32 / 4., 1. * grid.width as f32 / 4.),
+            particle_origin: Vec2::new(1.1 * domain.x / 4., 1. * domain.y / 4.),
             particle_velocity: Vec2::new(100.3, -1.3),
             particle_velocity_random_vec_a: Vec2::new(-0.0, -0.0),
             particle_velocity_random_vec_b: Vec2::new(0.0, 0.0),
-            particle_mass: STEEL_PARTICLE_MASS,
+            particle_density: STEEL_DENSITY,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: steel_thermal_properties(),
+            particle_contact_field: STEEL_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         steel_properties(),
         asset_server.load::<Image, &str>("steel_particle.png"),
@@ -69,46 +80,274 @@ pub(super) fn create_initial_spawners(
     ));
 
     // spawn tower on first turn.
-    // searching says the properties of wood/plywood are 9Gpa young's modulus 0.6Gpa shear modulus
-    // but has been increased to 18 Gpa and 6 Gpa to make it more rigid
     commands.spawn_bundle((
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Tower,
+            particles_per_cell: 1.,
             spawn_frequency: 99999999,
             max_particles: 50000,
             particle_duration: 500000,
-            particle_origin: Vec2::new(2.5 * grid.width as f32 / 4., 1.),
+            particle_origin: Vec2::new(2.5 * domain.x / 4., 1.),
             particle_velocity: Vec2::ZERO,
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: WOOD_PARTICLE_MASS,
-        },
-        NeoHookeanHyperElasticModel {
-            deformation_gradient: Default::default(),
-            elastic_lambda: 18. * 1000.,
-            elastic_mu: 6. * 1000.,
+            particle_density: WOOD_DENSITY,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: wood_thermal_properties(),
+            particle_contact_field: WOOD_CONTACT_FIELD,
+            particle_phase_change: None,
         },
+        wood_properties(),
         asset_server.load::<Image, &str>("wood_particle.png"),
         ParticleSpawnerTag,
     ));
 
+    // pour sand into a pile on the right
+    commands.spawn_bundle((
+        ParticleSpawnerInfo {
+            created_at: 0,
+            pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
+            spawn_frequency: 900,
+            max_particles: 75000,
+            particle_duration: 100000,
+            particle_origin: Vec2::new(3.4 * domain.x / 4., 3. * domain.y / 4.),
+            particle_velocity: Vec2::new(0., -20.),
+            particle_velocity_random_vec_a: Vec2::ZERO,
+            particle_velocity_random_vec_b: Vec2::ZERO,
+            particle_density: SAND_DENSITY,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: sand_thermal_properties(),
+            particle_contact_field: SAND_CONTACT_FIELD,
+            particle_phase_change: None,
+        },
+        sand_properties(),
+        asset_server.load::<Image, &str>("sand_particle.png"),
+        ParticleSpawnerTag,
+    ));
+
+    // throw snowballs at the tower
+    commands.spawn_bundle((
+        ParticleSpawnerInfo {
+            created_at: 0,
+            pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
+            spawn_frequency: 1200,
+            max_particles: 75000,
+            particle_duration: 40000,
+            particle_origin: Vec2::new(1.5 * domain.x / 4., 2. * domain.y / 4.),
+            particle_velocity: Vec2::new(60., 10.),
+            particle_velocity_random_vec_a: Vec2::ZERO,
+            particle_velocity_random_vec_b: Vec2::ZERO,
+            particle_density: SNOW_DENSITY,
+            particle_temperature: 263.,
+            particle_thermal_properties: snow_thermal_properties(),
+            particle_contact_field: SNOW_CONTACT_FIELD,
+            particle_phase_change: None,
+        },
+        snow_properties(),
+        asset_server.load::<Image, &str>("snow_particle.png"),
+        ParticleSpawnerTag,
+    ));
+
+    // drop ice cubes into the water to melt
+    commands.spawn_bundle((
+        ParticleSpawnerInfo {
+            created_at: 0,
+            pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
+            spawn_frequency: 1500,
+            max_particles: 75000,
+            particle_duration: 100000,
+            particle_origin: Vec2::new(0.5 * domain.x / 4., 2. * domain.y / 4.),
+            particle_velocity: Vec2::new(0., -10.),
+            particle_velocity_random_vec_a: Vec2::ZERO,
+            particle_velocity_random_vec_b: Vec2::ZERO,
+            particle_density: ICE_DENSITY,
+            particle_temperature: 258.,
+            particle_thermal_properties: ice_thermal_properties(),
+            particle_contact_field: ICE_CONTACT_FIELD,
+            particle_phase_change: Some(ice_phase_change(&asset_server)),
+        },
+        ice_properties(),
+        asset_server.load::<Image, &str>("ice_particle.png"),
+        ParticleSpawnerTag,
+    ));
+
+    // hot plate on the floor under the tower, and a cold corner top right
+    commands.spawn().insert(HeatSource {
+        min: Vec2::new(2.5 * domain.x / 4., 0.),
+        max: Vec2::new(3.75 * domain.x / 4., 0.06 * domain.y / 4.),
+        temperature: 600.,
+    });
+    commands.spawn().insert(HeatSource {
+        min: Vec2::new(3.5 * domain.x / 4., 3.5 * domain.y / 4.),
+        max: domain,
+        temperature: 250.,
+    });
+
+    // a crate floating in the water, and a seesaw in the middle
+    spawn_rigid_body(
+        &mut commands,
+        &grid,
+        crate_body(Vec2::new(0.97 * domain.x / 4., domain.y / 4.)),
+        Color::rgb(0.55, 0.35, 0.2),
+    );
+    spawn_rigid_body(
+        &mut commands,
+        &grid,
+        seesaw_body(Vec2::new(2. * domain.x / 4., 0.3 * domain.y / 4.)),
+        Color::rgb(0.4, 0.4, 0.45),
+    );
+
+    // funnel the sand into a narrow pile
+    spawn_collider(
+        &mut commands,
+        &mut images,
+        &grid,
+        Collider {
+            shape: ColliderShape::Union(
+                Box::new(ColliderShape::Capsule {
+                    a: Vec2::new(3.2 * domain.x / 4., 2.8 * domain.y / 4.),
+                    b: Vec2::new(3.42 * domain.x / 4., 2.6 * domain.y / 4.),
+                    radius: 1.5 * grid.dx,
+                }),
+                Box::new(ColliderShape::Capsule {
+                    a: Vec2::new(3.8 * domain.x / 4., 2.8 * domain.y / 4.),
+                    b: Vec2::new(3.58 * domain.x / 4., 2.6 * domain.y / 4.),
+                    radius: 1.5 * grid.dx,
+                }),
+            ),
+            boundary: BoundaryCondition::Separate,
+            friction: 0.3,
+        },
+        Color::rgb(0.5, 0.5, 0.55),
+    );
+
+    // bowl under the ice drop, the ice cubes melt in the rain it catches
+    spawn_collider(
+        &mut commands,
+        &mut images,
+        &grid,
+        Collider {
+            shape: ColliderShape::Difference(
+                Box::new(ColliderShape::Circle {
+                    center: Vec2::new(0.5 * domain.x / 4., 1.6 * domain.y / 4.),
+                    radius: domain.x / 16.,
+                }),
+                Box::new(ColliderShape::Circle {
+                    center: Vec2::new(0.5 * domain.x / 4., 1.6 * domain.y / 4. + 3. * grid.dx),
+                    radius: domain.x / 16.,
+                }),
+            ),
+            boundary: BoundaryCondition::Separate,
+            friction: 0.2,
+        },
+        Color::rgb(0.5, 0.5, 0.55),
+    );
+
+    // sticky ledge under the snowball throw, snow falling short stays on it
+    spawn_collider(
+        &mut commands,
+        &mut images,
+        &grid,
+        Collider {
+            shape: ColliderShape::Box {
+                center: Vec2::new(1.9 * domain.x / 4., 1.6 * domain.y / 4.),
+                half_extents: Vec2::new(0.125 * domain.x / 4., grid.dx),
+                rotation: 0.2,
+            },
+            boundary: BoundaryCondition::Sticky,
+            friction: 0.,
+        },
+        Color::rgb(0.5, 0.5, 0.55),
+    );
+
+    // ramp under the rain sending water towards the crate
+    spawn_collider(
+        &mut commands,
+        &mut images,
+        &grid,
+        Collider {
+            shape: ColliderShape::Polygon(vec![
+                Vec2::ZERO,
+                Vec2::new(0.94 * domain.x / 4., 0.),
+                Vec2::new(0., 0.47 * domain.y / 4.),
+            ]),
+            boundary: BoundaryCondition::Slip,
+            friction: 0.,
+        },
+        Color::rgb(0.5, 0.5, 0.55),
+    );
+
+    // paddle stirring the water
+    let paddle_center = Vec2::new(1.4 * domain.x / 4., 0.7 * domain.y / 4.);
+    if let Some(paddle) = spawn_collider(
+        &mut commands,
+        &mut images,
+        &grid,
+        Collider {
+            shape: ColliderShape::Box {
+                center: paddle_center,
+                half_extents: Vec2::new(0.19 * domain.x / 4., 1.5 * grid.dx),
+                rotation: 0.,
+            },
+            boundary: BoundaryCondition::Separate,
+            friction: 0.5,
+        },
+        Color::rgb(0.35, 0.35, 0.4),
+    ) {
+        commands
+            .entity(paddle)
+            .insert(KinematicMotion::spinning(paddle_center, 1.5));
+    }
+
+    // piston pushing the sand pile from the right wall, its path is loaded from a file
+    if let Some(piston) = spawn_collider(
+        &mut commands,
+        &mut images,
+        &grid,
+        Collider {
+            shape: ColliderShape::Box {
+                center: Vec2::new(3.81 * domain.x / 4., 0.16 * domain.y / 4.),
+                half_extents: Vec2::new(0.125 * domain.x / 4., 0.1 * domain.y / 4.),
+                rotation: 0.,
+            },
+            boundary: BoundaryCondition::Separate,
+            friction: 0.3,
+        },
+        Color::rgb(0.35, 0.35, 0.4),
+    ) {
+        // without its path the piston just stays put
+        match load_keyframes("paths/piston.path") {
+            Ok(piston_path) => {
+                commands
+                    .entity(piston)
+                    .insert(KinematicMotion::along_path(piston_path));
+            }
+            Err(reason) => error!("piston left in place, invalid path: {}", reason),
+        }
+    }
+
     // make it rain!
     commands.spawn_bundle((
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
             spawn_frequency: 78,
             max_particles: 75000,
             particle_duration: 100000,
-            particle_origin: Vec2::new(
-                0.5 * grid.width as f32 / 4. + 12.,
-                3. * grid.width as f32 / 4. + 16.,
-            ),
+            particle_origin: Vec2::new(0.5 * domain.x / 4. + 12., 3. * domain.y / 4. + 16.),
             particle_velocity: Vec2::new(-20., -55.),
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: LIQUID_PARTICLE_MASS,
+            particle_density: water_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         water_properties(),
         asset_server.load::<Image, &str>("liquid_particle.png"),
@@ -118,17 +357,19 @@ pub(super) fn create_initial_spawners(
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
             spawn_frequency: 478,
             max_particles: 75000,
             particle_duration: 100000,
-            particle_origin: Vec2::new(
-                0.5 * grid.width as f32 / 4. + 20.,
-                3. * grid.width as f32 / 4. + 12.,
-            ),
+            particle_origin: Vec2::new(0.5 * domain.x / 4. + 20., 3. * domain.y / 4. + 12.),
             particle_velocity: Vec2::new(-20., -35.),
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: LIQUID_PARTICLE_MASS,
+            particle_density: water_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         water_properties(),
         asset_server.load::<Image, &str>("liquid_particle.png"),
@@ -138,17 +379,19 @@ pub(super) fn create_initial_spawners(
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
             spawn_frequency: 478,
             max_particles: 75000,
             particle_duration: 100000,
-            particle_origin: Vec2::new(
-                0.5 * grid.width as f32 / 4. - 16.,
-                3. * grid.width as f32 / 4.,
-            ),
+            particle_origin: Vec2::new(0.5 * domain.x / 4. - 16., 3. * domain.y / 4.),
             particle_velocity: Vec2::new(30., -35.),
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: LIQUID_PARTICLE_MASS,
+            particle_density: water_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         water_properties(),
         asset_server.load::<Image, &str>("liquid_particle.png"),
@@ -158,17 +401,19 @@ pub(super) fn create_initial_spawners(
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
             spawn_frequency: 800,
             max_particles: 75000,
             particle_duration: 100000,
-            particle_origin: Vec2::new(
-                0.5 * grid.width as f32 / 4. - 8.,
-                3. * grid.width as f32 / 4.,
-            ),
+            particle_origin: Vec2::new(0.5 * domain.x / 4. - 8., 3. * domain.y / 4.),
             particle_velocity: Vec2::new(40., -45.),
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: LIQUID_PARTICLE_MASS,
+            particle_density: water_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         water_properties(),
         asset_server.load::<Image, &str>("liquid_particle.png"),
@@ -178,14 +423,19 @@ pub(super) fn create_initial_spawners(
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
             spawn_frequency: 700,
             max_particles: 75000,
             particle_duration: 100000,
-            particle_origin: Vec2::new(0.5 * grid.width as f32 / 4., 3. * grid.width as f32 / 4.),
+            particle_origin: Vec2::new(0.5 * domain.x / 4., 3. * domain.y / 4.),
             particle_velocity: Vec2::new(50., -45.),
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: LIQUID_PARTICLE_MASS,
+            particle_density: water_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         water_properties(),
         asset_server.load::<Image, &str>("liquid_particle.png"),
@@ -195,61 +445,140 @@ pub(super) fn create_initial_spawners(
         ParticleSpawnerInfo {
             created_at: 0,
             pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
             spawn_frequency: 600,
             max_particles: 75000,
             particle_duration: 100000,
-            particle_origin: Vec2::new(
-                0.5 * grid.width as f32 / 4. + 8.,
-                3. * grid.width as f32 / 4.,
-            ),
+            particle_origin: Vec2::new(0.5 * domain.x / 4. + 8., 3. * domain.y / 4.),
             particle_velocity: Vec2::new(10., -45.),
             particle_velocity_random_vec_a: Vec2::ZERO,
             particle_velocity_random_vec_b: Vec2::ZERO,
-            particle_mass: LIQUID_PARTICLE_MASS,
+            particle_density: water_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
         },
         water_properties(),
         asset_server.load::<Image, &str>("liquid_particle.png"),
         ParticleSpawnerTag,
     ));
+
+    // pour oil into the water, it floats to the top
+    commands.spawn_bundle((
+        ParticleSpawnerInfo {
+            created_at: 0,
+            pattern: SpawnerPattern::Cube,
+            particles_per_cell: 1.,
+            spawn_frequency: 900,
+            max_particles: 75000,
+            particle_duration: 100000,
+            particle_origin: Vec2::new(0.5 * domain.x / 4. + 4., 3. * domain.y / 4. + 8.),
+            particle_velocity: Vec2::new(20., -40.),
+            particle_velocity_random_vec_a: Vec2::ZERO,
+            particle_velocity_random_vec_b: Vec2::ZERO,
+            particle_density: oil_properties().rest_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
+        },
+        oil_properties(),
+        asset_server.load::<Image, &str>("oil_particle.png"),
+        ParticleSpawnerTag,
+    ));
 }
 
-pub(super) fn tick_spawners(
+// rigid bodies are drawn in grid cells like the particles
+pub(super) fn spawn_rigid_body(
+    commands: &mut Commands,
+    grid: &Grid,
+    body: RigidBody,
+    color: Color,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(body.half_extents * 2. * grid.inv_dx()),
+                ..Default::default()
+            },
+            // draw on top of particles
+            transform: Transform::from_translation((body.position * grid.inv_dx()).extend(1.)),
+            ..Default::default()
+        })
+        .insert(body);
+}
+
+// colliders are drawn by rasterizing their shape into a texture covering the grid, sized in cells.
+// invalid shapes are logged and skipped, so one bad collider doesn't take the whole scene down.
+pub(super) fn spawn_collider(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    grid: &Grid,
+    collider: Collider,
+    color: Color,
+) -> Option<Entity> {
+    if let Err(reason) = collider.shape.validate() {
+        error!("skipping invalid collider shape: {}", reason);
+        return None;
+    }
+    let pixels_per_cell = 2;
+    let width = grid.width * pixels_per_cell;
+    let height = grid.height * pixels_per_cell;
+    let rgba = color.as_rgba_f32().map(|channel| (channel * 255.) as u8);
+    let mut data = vec![0; width * height * 4];
+    for row in 0..height {
+        for column in 0..width {
+            // image rows go from the top down
+            let point = Vec2::new(
+                (column as f32 + 0.5) / pixels_per_cell as f32,
+                (height - row) as f32 / pixels_per_cell as f32,
+            ) * grid.dx;
+            if collider.shape.distance(point) < 0. {
+                let i = (row * width + column) * 4;
+                data[i..i + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+    let image = Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    let entity = commands
+        .spawn_bundle(SpriteBundle {
+            texture: images.add(image),
+            sprite: Sprite {
+                custom_size: Some(grid.size()),
+                ..Default::default()
+            },
+            transform: Transform::from_translation((grid.size() / 2.).extend(1.)),
+            ..Default::default()
+        })
+        .insert(collider)
+        .id();
+    Some(entity)
+}
+
+pub(super) fn tick_spawners<M: ConstitutiveModel>(
     mut commands: Commands,
     world: Res<WorldState>,
     grid: Res<Grid>,
     particles: Query<(), With<ParticleTag>>,
-    spawners_solids: Query<
-        (
-            &ParticleSpawnerInfo,
-            &NeoHookeanHyperElasticModel,
-            &Handle<Image>,
-        ),
-        With<ParticleSpawnerTag>,
-    >,
-    spawners_fluids: Query<
-        (&ParticleSpawnerInfo, &NewtonianFluidModel, &Handle<Image>),
-        With<ParticleSpawnerTag>,
-    >,
+    spawners: Query<(&ParticleSpawnerInfo, &M, &Handle<Image>), With<ParticleSpawnerTag>>,
 ) {
     // todo recreate spiral spawn pattern - rate per spawn and rotation per spawn
 
-    spawners_solids.for_each(|(spawner_info, particle_properties, texture)| {
-        if (world.current_tick - spawner_info.created_at) % spawner_info.spawn_frequency == 0
-            && particles.iter().count() < spawner_info.max_particles
-        {
-            spawn_particles(
-                spawner_info,
-                *particle_properties,
-                &mut commands,
-                texture.clone(),
-                &world,
-                &grid,
-            );
-        }
-    });
-
-    spawners_fluids.for_each(|(spawner_info, particle_properties, texture)| {
-        if (world.current_tick - spawner_info.created_at) % spawner_info.spawn_frequency == 0
+    spawners.for_each(|(spawner_info, particle_properties, texture)| {
+        if (world.current_tick - spawner_info.created_at)
+            .is_multiple_of(spawner_info.spawn_frequency)
             && particles.iter().count() < spawner_info.max_particles
         {
             spawn_particles(
@@ -264,45 +593,60 @@ pub(super) fn tick_spawners(
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_particle(
     commands: &mut Commands,
-    grid_width: usize,
-    cm: impl ConstitutiveModel + Copy,
+    grid: &Grid,
+    cm: impl ConstitutiveModel,
     spawner_info: &ParticleSpawnerInfo,
     spawn_offset: Vec2,
     vel: Option<Vec2>,
     texture: Handle<Image>,
     created_at: usize,
 ) {
-    let particle_position = spawner_info.particle_origin + spawn_offset;
+    // patterns lay particles out in units of the particle spacing
+    let spacing = grid.dx / spawner_info.particles_per_cell.sqrt();
+    let particle_position = spawner_info.particle_origin + spawn_offset * spacing;
 
-    let min = 3;
-    let max = grid_width - 4;
-    if particle_position.x <= min as f32 || particle_position.x >= max as f32 {
+    let min = Vec2::splat(3. * grid.dx);
+    let max = grid.domain_size() - Vec2::splat(4. * grid.dx);
+    if particle_position.x <= min.x || particle_position.x >= max.x {
         return;
     }
-    if particle_position.y <= min as f32 || particle_position.y >= max as f32 {
+    if particle_position.y <= min.y || particle_position.y >= max.y {
         return;
     }
 
-    cm.new_particle(
+    // each particle fills an equal share of a cell, over a depth of 1m
+    let volume = grid.dx * grid.dx / spawner_info.particles_per_cell;
+    let particle = cm.new_particle(
         commands,
         texture.clone(),
         particle_position,
-        spawner_info.particle_mass,
+        spawner_info.particle_density * volume,
+        volume,
         created_at,
         vel,
         Some(spawner_info.particle_duration),
     );
+    commands.entity(particle).insert_bundle((
+        Temperature(spawner_info.particle_temperature),
+        spawner_info.particle_thermal_properties,
+        CellHeatContributions([NO_HEAT_CHANGE; MAX_STENCIL_SIZE], 0),
+        ContactField(spawner_info.particle_contact_field),
+    ));
+    if let Some(phase_change) = &spawner_info.particle_phase_change {
+        commands.entity(particle).insert(phase_change.clone());
+    }
 }
 
 pub(super) fn spawn_particles(
     spawner_info: &ParticleSpawnerInfo,
-    cm: impl ConstitutiveModel + Copy,
+    cm: impl ConstitutiveModel,
     commands: &mut Commands,
     texture: Handle<Image>,
     world: &WorldState,
-    grid: &Res<Grid>,
+    grid: &Grid,
 ) {
     // todo prevent out-of-bounds spawning here.
 
@@ -322,7 +666,7 @@ pub(super) fn spawn_particles(
         SpawnerPattern::SingleParticle => {
             spawn_particle(
                 commands,
-                grid.width,
+                grid,
                 cm,
                 spawner_info,
                 Vec2::ZERO,
@@ -335,7 +679,7 @@ pub(super) fn spawn_particles(
             for x in 0..100 {
                 spawn_particle(
                     commands,
-                    grid.width,
+                    grid,
                     cm,
                     spawner_info,
                     Vec2::new(x as f32, 0.),
@@ -349,7 +693,7 @@ pub(super) fn spawn_particles(
             for y in 0..15 {
                 spawn_particle(
                     commands,
-                    grid.width,
+                    grid,
                     cm,
                     spawner_info,
                     Vec2::new(0., y as f32),
@@ -364,7 +708,7 @@ pub(super) fn spawn_particles(
                 for y in 0..15 {
                     spawn_particle(
                         commands,
-                        grid.width,
+                        grid,
                         cm,
                         spawner_info,
                         Vec2::new(x as f32 + 0.001, y as f32 + 0.001),
@@ -380,7 +724,7 @@ pub(super) fn spawn_particles(
                 for y in 0..90 {
                     spawn_particle(
                         commands,
-                        grid.width,
+                        grid,
                         cm,
                         spawner_info,
                         Vec2::new(x as f32, y as f32),
@@ -410,10 +754,7 @@ pub(super) fn spawn_particles(
 
                     // rotate by angle about triangle tip (15, 0)
                     let pivot: Vec2 = Vec2::new(15., 0.);
-                    let pos: Vec2 = Vec2::new(
-                        (0.001 + pivot.x - x as f32) / 4.,
-                        (0.001 + pivot.y + ya as f32) / 4.,
-                    );
+                    let pos: Vec2 = Vec2::new(0.001 + pivot.x - x as f32, 0.001 + pivot.y + ya);
 
                     // 1. translate point back to origin
                     let pos_rel_origin: Vec2 = Vec2::new(pos.x - pivot.x, pos.y - pivot.y);
@@ -429,7 +770,7 @@ pub(super) fn spawn_particles(
 
                     spawn_particle(
                         commands,
-                        grid.width,
+                        grid,
                         cm,
                         spawner_info,
                         pos_rotated,
@@ -442,3 +783,85 @@ pub(super) fn spawn_particles(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+
+    fn spawner(particles_per_cell: f32, particle_density: f32) -> ParticleSpawnerInfo {
+        ParticleSpawnerInfo {
+            created_at: 0,
+            pattern: SpawnerPattern::SingleParticle,
+            particles_per_cell,
+            spawn_frequency: 1,
+            max_particles: 1,
+            particle_duration: 1,
+            particle_origin: Vec2::splat(8.),
+            particle_velocity: Vec2::ZERO,
+            particle_velocity_random_vec_a: Vec2::ZERO,
+            particle_velocity_random_vec_b: Vec2::ZERO,
+            particle_density,
+            particle_temperature: DEFAULT_TEMPERATURE,
+            particle_thermal_properties: water_thermal_properties(),
+            particle_contact_field: FLUID_CONTACT_FIELD,
+            particle_phase_change: None,
+        }
+    }
+
+    fn spawn_one(
+        cm: impl ConstitutiveModel,
+        spawner_info: &ParticleSpawnerInfo,
+    ) -> (World, Entity) {
+        let grid = Grid::new(Vec2::splat(16.), 0.5);
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        spawn_particle(
+            &mut commands,
+            &grid,
+            cm,
+            spawner_info,
+            Vec2::ZERO,
+            None,
+            Handle::default(),
+            0,
+        );
+        queue.apply(&mut world);
+        let particle = world
+            .query_filtered::<Entity, With<ParticleTag>>()
+            .single(&world);
+        (world, particle)
+    }
+
+    #[test]
+    fn particles_share_their_cell_by_volume_and_density() {
+        let dx = 0.5;
+        for particles_per_cell in [1., 4., 9.] {
+            let volume = dx * dx / particles_per_cell;
+
+            let (world, steel) = spawn_one(
+                steel_properties(),
+                &spawner(particles_per_cell, STEEL_DENSITY),
+            );
+            assert_eq!(world.get::<InitialVolume>(steel).unwrap().0, volume);
+            assert_eq!(world.get::<Mass>(steel).unwrap().0, STEEL_DENSITY * volume);
+            assert!(world.get::<RestVolume>(steel).is_none());
+
+            let water = water_properties();
+            let (world, drop) = spawn_one(water, &spawner(particles_per_cell, water.rest_density));
+            assert_eq!(world.get::<InitialVolume>(drop).unwrap().0, volume);
+            assert_eq!(
+                world.get::<Mass>(drop).unwrap().0,
+                water.rest_density * volume
+            );
+            let rest_volume = world.get::<RestVolume>(drop).unwrap().0;
+            assert!(
+                (rest_volume - volume).abs() < 1e-6 * volume,
+                "water at rest density fills {} instead of {} m^2",
+                rest_volume,
+                volume
+            );
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::world::*;
+
+pub(super) const TIME_STEP_DIAGNOSTIC: DiagnosticId =
+    DiagnosticId::from_u128(183562094721190473582947165301845712093);
+
+pub(super) fn setup_time_step_diagnostic(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(TIME_STEP_DIAGNOSTIC, "dt", 20));
+}
+
+// fastest particle speed plus wave speed for particles made of material M, from the velocities at the end of a step.
+// run_substeps picks the next step's dt from it.
+pub(super) fn measure_signal_speed<M: ConstitutiveModel>(
+    mut world: ResMut<WorldState>,
+    particles: Query<(&Velocity, &Mass, &InitialVolume, &M), With<ParticleTag>>,
+) {
+    if !world.adaptive_dt {
+        return;
+    }
+    let signal_speed = particles
+        .iter()
+        .fold(0., |max, (velocity, mass, initial_volume, pp)| {
+            // implicit solids are stable past their wave speed, only their motion limits dt
+            let wave_speed = if world.implicit && M::IMPLICIT {
+                0.
+            } else {
+                pp.wave_speed(mass.0 / initial_volume.0)
+            };
+            f32::max(max, velocity.0.length() + wave_speed)
+        });
+    world.max_signal_speed = world.max_signal_speed.max(signal_speed);
+}
+
+// CFL condition, information must not cross more than a fraction of a cell in one step
+pub(super) fn choose_time_step(world: &mut WorldState, dx: f32) {
+    if world.adaptive_dt {
+        let dt = if world.max_signal_speed > 0. {
+            world.cfl * dx / world.max_signal_speed
+        } else {
+            world.max_dt
+        };
+        world.dt = dt.clamp(world.min_dt, world.max_dt);
+    }
+}
+
+pub(super) fn record_time_step(world: Res<WorldState>, mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add_measurement(TIME_STEP_DIAGNOSTIC, || world.dt as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive_world(max_signal_speed: f32) -> WorldState {
+        let mut world = WorldState::default();
+        world.adaptive_dt = true;
+        world.cfl = 0.5;
+        world.min_dt = 1e-4;
+        world.max_dt = 1e-2;
+        world.max_signal_speed = max_signal_speed;
+        world
+    }
+
+    #[test]
+    fn time_step_keeps_signals_within_a_fraction_of_a_cell() {
+        let mut world = adaptive_world(100.);
+        choose_time_step(&mut world, 0.5);
+        assert_eq!(world.dt, 0.5 * 0.5 / 100.);
+    }
+
+    #[test]
+    fn time_step_is_clamped() {
+        let mut world = adaptive_world(1e6);
+        choose_time_step(&mut world, 0.5);
+        assert_eq!(world.dt, world.min_dt);
+
+        let mut world = adaptive_world(1.);
+        choose_time_step(&mut world, 0.5);
+        assert_eq!(world.dt, world.max_dt);
+    }
+
+    #[test]
+    fn nothing_moving_takes_the_largest_step() {
+        let mut world = adaptive_world(0.);
+        choose_time_step(&mut world, 0.5);
+        assert_eq!(world.dt, world.max_dt);
+    }
+
+    #[test]
+    fn fixed_time_step_is_left_alone() {
+        let mut world = adaptive_world(1e6);
+        world.adaptive_dt = false;
+        let dt = world.dt;
+        choose_time_step(&mut world, 0.5);
+        assert_eq!(world.dt, dt);
+    }
+}
@@ -10,75 +10,232 @@ use crate::world::*;
 pub(super) fn grid_to_particles(
     world: Res<WorldState>,
     grid: Res<Grid>,
-    mut particles: Query<(&mut Position, &mut Velocity, &mut AffineMomentum), With<ParticleTag>>,
+    mut particles: Query<
+        (
+            &mut Position,
+            &mut Velocity,
+            &mut AffineMomentum,
+            &mut VelocityGradient,
+            &ContactField,
+        ),
+        With<ParticleTag>,
+    >,
 ) {
     particles.par_for_each_mut(
         PAR_BATCH_SIZE,
-        |(mut position, mut velocity, mut affine_momentum)| {
-            //// reset particle velocity. we calculate it from scratch each step using the grid
-            velocity.0 = Vec2::ZERO;
-
-            let cell_x: u32 = position.0.x as u32;
-            let cell_y: u32 = position.0.y as u32;
-            let cell_diff = Vec2::new(
-                position.0.x - cell_x as f32 - 0.5,
-                position.0.y - cell_y as f32 - 0.5,
-            );
-            let weights = quadratic_interpolation_weights(cell_diff);
+        |(mut position, mut velocity, mut affine_momentum, mut velocity_gradient, field)| {
+            let stencil = grid.kernel.stencil(&grid, position.0);
 
+            // interpolated grid velocity, and for FLIP the interpolated change in grid velocity this step
+            let mut pic_velocity = Vec2::ZERO;
+            let mut velocity_change = Vec2::ZERO;
             // affine per-particle momentum matrix from APIC / MLS-MPM.
             // see APIC paper (https://web.archive.org/web/20190427165435/https://www.math.ucla.edu/~jteran/papers/JSSTS15.pdf), page 6
             // below equation 11 for clarification. this is calculating C = B * (D^-1) for APIC equation 8,
-            // where B is calculated in the inner loop at (D^-1) = 4 is a constant when using quadratic interpolation functions
-            let mut b = Mat2::ZERO;
-            // for all surrounding 9 cells
-            for gx in 0..3 {
-                for gy in 0..3 {
-                    let weight = weights[gx].x * weights[gy].y;
-                    let cell_pos_x = (cell_x as i32 + gx as i32) - 1;
-                    let cell_pos_y = (cell_y as i32 + gy as i32) - 1;
-                    let cell_dist = Vec2::new(
-                        cell_pos_x as f32 - position.0.x + 0.5,
-                        cell_pos_y as f32 - position.0.y + 0.5,
-                    );
+            // summing velocity * (D^-1 * weight * cell_dist)^T over the stencil, see Kernel::d_inverse
+            let mut c = Mat2::ZERO;
+            let mut gradient = Mat2::ZERO;
+            // for all surrounding cells
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    let weight = stencil.weight(gx, gy);
+                    let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                    let cell_dist = grid.node_position(cell_pos_x, cell_pos_y) - position.0;
 
                     let cell_at_index =
                         // todo why was this fine when using grid directly?
-                        grid.index_at(cell_pos_x as usize, cell_pos_y as usize);
-                    let weighted_velocity = grid.cells[cell_at_index].velocity * weight;
-                    b += weighted_velocity_and_cell_dist_to_term(weighted_velocity, cell_dist);
-                    velocity.0 += weighted_velocity;
+                        grid.stencil_index(cell_pos_x, cell_pos_y);
+                    let cell = &grid.cells[cell_at_index];
+                    let (cell_velocity, previous_velocity) = if world.multi_field_contact {
+                        (
+                            cell.field_velocity[field.0],
+                            cell.previous_field_velocity[field.0],
+                        )
+                    } else {
+                        (cell.velocity, cell.previous_velocity)
+                    };
+                    c += weighted_velocity_and_cell_dist_to_term(
+                        cell_velocity,
+                        stencil.mls_weight_gradient(&grid, gx, gy, cell_dist),
+                    );
+                    pic_velocity += cell_velocity * weight;
+                    velocity_change += (cell_velocity - previous_velocity) * weight;
+
+                    if world.transfer != TransferScheme::MlsMpm {
+                        let weight_gradient =
+                            stencil.weight_gradient(&grid, world.transfer, gx, gy, cell_dist);
+                        gradient +=
+                            weighted_velocity_and_cell_dist_to_term(cell_velocity, weight_gradient);
+                    }
                 }
             }
 
-            affine_momentum.0 = b * 4.0;
-
-            // advect particles
-            position.0 += velocity.0 * world.dt;
+            match world.transfer {
+                TransferScheme::Pic | TransferScheme::PicFlip => {
+                    affine_momentum.0 = Mat2::ZERO;
+                    velocity_gradient.0 = gradient;
+                }
+                TransferScheme::Apic => {
+                    affine_momentum.0 = c;
+                    velocity_gradient.0 = gradient;
+                }
+                TransferScheme::MlsMpm => {
+                    affine_momentum.0 = c;
+                    velocity_gradient.0 = affine_momentum.0;
+                }
+            }
 
-            //// safety clamp to ensure particles don't exit simulation domain
-            position.0.x = position.0.x.clamp(1.0, (grid.width - 2) as f32);
-            position.0.y = position.0.y.clamp(1.0, (grid.width - 2) as f32);
+            // advect particles with the grid velocity, FLIP only changes the velocity they carry
+            position.0 += pic_velocity * world.dt;
 
-            // predictive boundary velocity cap
-            let wall_min: f32 = 3.0;
-            let wall_max: f32 = (grid.width - 1) as f32 - wall_min;
+            // ensure particles don't exit simulation domain.
+            // the wall cap only slows the grid velocity, FLIP would add it to the particle's own velocity every step
+            grid.boundaries.confine(
+                &mut position.0,
+                &mut pic_velocity,
+                grid.width,
+                grid.height,
+                grid.dx,
+                grid.kernel,
+            );
 
-            // apply boundary conditions about 0.1 seconds before reaching edge
-            let dt_multiplier = 0.1 / world.dt;
-            let position_next = position.0 + velocity.0 * world.dt * dt_multiplier;
-            if position_next.x < wall_min {
-                velocity.0.x += wall_min - position_next.x;
-            }
-            if position_next.x > wall_max {
-                velocity.0.x += wall_max - position_next.x;
-            }
-            if position_next.y < wall_min {
-                velocity.0.y += wall_min - position_next.y;
-            }
-            if position_next.y > wall_max {
-                velocity.0.y += wall_max - position_next.y;
-            }
+            velocity.0 = if world.transfer == TransferScheme::PicFlip {
+                (velocity.0 + velocity_change) * world.flip_ratio
+                    + pic_velocity * (1. - world.flip_ratio)
+            } else {
+                pic_velocity
+            };
         },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::schedule::IntoSystemDescriptor;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use super::*;
+
+    // every cell moves at velocity and moved at previous_velocity last step
+    fn uniform_grid(velocity: Vec2, previous_velocity: Vec2) -> Grid {
+        let mut grid = Grid::new(Vec2::splat(16.), 1.);
+        for cell in grid.cells.iter_mut() {
+            cell.mass = 1.;
+            cell.velocity = velocity;
+            cell.previous_velocity = previous_velocity;
+        }
+        grid
+    }
+
+    // one G2P step of a particle at position moving at velocity, returns its new velocity and affine momentum
+    fn g2p(
+        grid: Grid,
+        transfer: TransferScheme,
+        flip_ratio: f32,
+        position: Vec2,
+        velocity: Vec2,
+    ) -> (Vec2, Mat2) {
+        ComputeTaskPool::init(TaskPool::default);
+        let mut state = WorldState::default();
+        state.transfer = transfer;
+        state.flip_ratio = flip_ratio;
+        let mut world = World::new();
+        world.insert_resource(state);
+        world.insert_resource(grid);
+        let particle = world
+            .spawn()
+            .insert_bundle((
+                Position(position),
+                Velocity(velocity),
+                AffineMomentum(Mat2::IDENTITY),
+                VelocityGradient(Mat2::ZERO),
+                ContactField(0),
+                ParticleTag,
+            ))
+            .id();
+        run_system(&mut world, grid_to_particles);
+        (
+            world.get::<Velocity>(particle).unwrap().0,
+            world.get::<AffineMomentum>(particle).unwrap().0,
+        )
+    }
+
+    fn run_system<Params>(world: &mut World, system: impl IntoSystemDescriptor<Params>) {
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(system);
+        stage.run(world);
+    }
+
+    fn assert_close(actual: Vec2, expected: Vec2) {
+        assert!(
+            (actual - expected).length() < 1e-4,
+            "velocity {} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn pic_takes_the_grid_velocity_and_drops_affine_momentum() {
+        let grid = uniform_grid(Vec2::new(1., 2.), Vec2::new(5., 5.));
+        let (velocity, affine_momentum) = g2p(
+            grid,
+            TransferScheme::Pic,
+            0.,
+            Vec2::new(8.3, 7.6),
+            Vec2::new(-3., 4.),
+        );
+        assert_close(velocity, Vec2::new(1., 2.));
+        assert_eq!(affine_momentum, Mat2::ZERO);
+    }
+
+    #[test]
+    fn flip_adds_the_grid_velocity_change_to_the_particle_velocity() {
+        let (grid_velocity, previous_velocity) = (Vec2::new(1., 2.), Vec2::new(0.5, 3.));
+        let particle_velocity = Vec2::new(-3., 4.);
+        let flip_ratio = 0.75;
+        let (velocity, affine_momentum) = g2p(
+            uniform_grid(grid_velocity, previous_velocity),
+            TransferScheme::PicFlip,
+            flip_ratio,
+            Vec2::new(8.3, 7.6),
+            particle_velocity,
+        );
+        let flip = particle_velocity + grid_velocity - previous_velocity;
+        assert_close(
+            velocity,
+            flip * flip_ratio + grid_velocity * (1. - flip_ratio),
+        );
+        assert_eq!(affine_momentum, Mat2::ZERO);
+    }
+
+    #[test]
+    fn flip_only_blends_in_the_wall_cap() {
+        // falling fast just above the floor, the wall cap slows the grid velocity
+        let falling = Vec2::new(0., -40.);
+        let grid = uniform_grid(falling, falling);
+        let mut position = Vec2::new(8.3, 4.2);
+        let mut capped = falling;
+        let dt = WorldState::default().dt;
+        position += falling * dt;
+        grid.boundaries.confine(
+            &mut position,
+            &mut capped,
+            grid.width,
+            grid.height,
+            grid.dx,
+            grid.kernel,
+        );
+        assert!(capped.y > falling.y, "the test needs the wall cap to apply");
+
+        let flip_ratio = 0.95;
+        let (velocity, _) = g2p(
+            grid,
+            TransferScheme::PicFlip,
+            flip_ratio,
+            Vec2::new(8.3, 4.2),
+            falling,
+        );
+        assert_close(velocity, falling * flip_ratio + capped * (1. - flip_ratio));
+    }
+}
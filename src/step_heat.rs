@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::defaults::*;
+use crate::grid::*;
+use crate::world::*;
+
+// scatter particle heat onto the grid, weighted the same way as mass.
+pub(super) fn particles_to_grid_heat(
+    grid: Res<Grid>,
+    mut particles: Query<
+        (
+            &Position,
+            &Mass,
+            &Temperature,
+            &ThermalProperties,
+            &mut CellHeatContributions,
+        ),
+        With<ParticleTag>,
+    >,
+) {
+    let num_particles = particles.iter().count();
+    if num_particles < 1 {
+        return;
+    }
+    particles.par_for_each_mut(
+        PAR_BATCH_SIZE,
+        |(position, mass, temperature, thermal, mut hc)| {
+            let stencil = grid.kernel.stencil(&grid, position.0);
+
+            // collect heat changes for surrounding cells.
+            hc.1 = stencil.width * stencil.width;
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    let weight = stencil.weight(gx, gy);
+                    let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                    let cell_at_index = grid.stencil_index(cell_pos_x, cell_pos_y);
+
+                    let thermal_mass = weight * mass.0 * thermal.heat_capacity;
+                    hc.0[stencil.slot(gx, gy)] = GridHeatChange(
+                        cell_at_index,
+                        thermal_mass,
+                        thermal_mass * temperature.0,
+                        thermal_mass * thermal.conductivity,
+                    );
+                }
+            }
+        },
+    );
+}
+
+pub(super) fn apply_heat_contributions(
+    mut grid: ResMut<Grid>,
+    particles: Query<(&CellHeatContributions,), With<ParticleTag>>,
+) {
+    particles.for_each(|hc| {
+        for change in hc.0.changes() {
+            let cell = &mut grid.cells[change.0];
+            cell.thermal_mass += change.1;
+            cell.temperature += change.2;
+            cell.conductivity += change.3;
+        }
+    });
+}
+
+pub(super) fn conduct_heat(
+    mut grid: ResMut<Grid>,
+    world: Res<WorldState>,
+    sources: Query<&HeatSource>,
+) {
+    let sources: Vec<HeatSource> = sources.iter().copied().collect();
+    grid.conduct_heat(world.dt, &sources);
+}
+
+// gather the temperature change back onto particles.
+// only the change is interpolated so particles don't lose detail to grid smoothing every step.
+pub(super) fn grid_to_particles_heat(
+    grid: Res<Grid>,
+    mut particles: Query<(&Position, &mut Temperature), With<ParticleTag>>,
+) {
+    particles.par_for_each_mut(PAR_BATCH_SIZE, |(position, mut temperature)| {
+        let stencil = grid.kernel.stencil(&grid, position.0);
+
+        let mut temperature_change = 0.0;
+        for gx in 0..stencil.width {
+            for gy in 0..stencil.width {
+                let weight = stencil.weight(gx, gy);
+                let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                let cell_at_index = grid.stencil_index(cell_pos_x, cell_pos_y);
+                temperature_change += grid.cells[cell_at_index].temperature_change * weight;
+            }
+        }
+        temperature.0 += temperature_change;
+    });
+}
@@ -0,0 +1,22 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::grid::Grid;
+use crate::world::*;
+
+// advance kinematic colliders, and move their sprite with them.
+pub(super) fn move_colliders(
+    world: Res<WorldState>,
+    grid: Res<Grid>,
+    mut colliders: Query<(&mut KinematicMotion, &mut Transform), With<Collider>>,
+) {
+    // collider sprites cover the whole grid, centered on it at rest. they are drawn in grid cells.
+    let center = grid.domain_size() / 2.;
+    colliders.for_each_mut(|(mut motion, mut transform)| {
+        motion.advance(world.dt);
+        let position = motion.to_world_position(center) * grid.inv_dx();
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        transform.rotation = Quat::from_rotation_z(motion.rotation);
+    });
+}
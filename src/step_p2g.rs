@@ -1,6 +1,3 @@
-use std::ops::{Add, Mul, Sub};
-
-use bevy::math::Mat2;
 use bevy::prelude::*;
 
 use crate::components::*;
@@ -8,157 +5,307 @@ use crate::defaults::*;
 use crate::grid::*;
 use crate::world::*;
 
-pub(super) fn particles_to_grid_solids(
+// particle state read by P2G, damage only exists on breakable solids and rest volume on fluids
+type StressedParticle<M> = (
+    &'static Position,
+    &'static Mass,
+    &'static InitialVolume,
+    &'static VelocityGradient,
+    &'static M,
+    Option<&'static Damage>,
+    Option<&'static RestVolume>,
+    &'static mut CellMassMomentumContributions,
+);
+
+// P2G MPM step, scatters the stress of every particle made of material M onto the grid.
+pub(super) fn particles_to_grid<M: ConstitutiveModel>(
     grid: Res<Grid>,
     world: Res<WorldState>,
-    mut particles_solid: Query<
-        (
-            &Position,
-            &Mass,
-            &AffineMomentum,
-            &NeoHookeanHyperElasticModel,
-            &mut CellMassMomentumContributions,
-        ),
-        With<ParticleTag>,
-    >,
+    mut particles: Query<StressedParticle<M>, With<ParticleTag>>,
 ) {
-    let num_particles = particles_solid.iter().count();
+    let num_particles = particles.iter().count();
     if num_particles < 1 {
         return;
     }
-    particles_solid.par_for_each_mut(PAR_BATCH_SIZE, |(position, mass, _, pp, mut mmc)| {
-        let cell_x: u32 = position.0.x as u32;
-        let cell_y: u32 = position.0.y as u32;
-        let cell_diff = Vec2::new(
-            position.0.x - cell_x as f32 - 0.5,
-            position.0.y - cell_y as f32 - 0.5,
-        );
-        let weights = quadratic_interpolation_weights(cell_diff);
-
-        // check surrounding 9 cells to get volume from density
-        let mut density: f32 = 0.0;
-        for gx in 0..3 {
-            for gy in 0..3 {
-                let weight = weights[gx].x * weights[gy].y;
-                let cell_pos_x = (cell_x as i32 + gx as i32) - 1;
-                let cell_pos_y = (cell_y as i32 + gy as i32) - 1;
-                let cell_at_index = grid.index_at(cell_pos_x as usize, cell_pos_y as usize);
-                density += grid.cells[cell_at_index].mass * weight;
-            }
-        }
+    particles.par_for_each_mut(
+        PAR_BATCH_SIZE,
+        |(position, mass, initial_volume, velocity_gradient, pp, damage, rest_volume, mut mmc)| {
+            let stencil = grid.kernel.stencil(&grid, position.0);
+
+            // fluids check surrounding cells to get volume from density.
+            // they also gather the mean fluid rest density and the smallest interfacial tension around them.
+            // gradients use the MLS approximation of the weight gradient, D^-1 * weight * cell_dist.
+            // density is the color field for surface tension. it is built on the grid by update_cells, only its
+            // gradient is sampled here, so the surface stress is scattered like every other particle stress:
+            // momentum is conserved and each particle uses the coefficient of its own material.
+            let cell_area = grid.dx * grid.dx;
+            let own_rest_density = pp.rest_density().unwrap_or(0.);
+            let mut density: f32 = 0.0;
+            let mut density_gradient = Vec2::ZERO;
+            let mut rest_density_gradient = Vec2::ZERO;
+            let mut rest_density_range = Vec2::new(f32::MAX, f32::MIN);
+            let mut interfacial_tension = pp.interfacial_tension();
+            if rest_volume.is_some() {
+                for gx in 0..stencil.width {
+                    for gy in 0..stencil.width {
+                        let weight = stencil.weight(gx, gy);
+                        let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                        let cell_dist = grid.node_position(cell_pos_x, cell_pos_y) - position.0;
+                        let weight_gradient = stencil.mls_weight_gradient(&grid, gx, gy, cell_dist);
+                        let cell_at_index = grid.stencil_index(cell_pos_x, cell_pos_y);
+                        let cell = &grid.cells[cell_at_index];
+
+                        let cell_density = cell.mass / cell_area;
+                        density += cell_density * weight;
+                        density_gradient += weight_gradient * cell_density;
 
-        let volume = mass.0 / density;
-
-        let j: f32 = pp.deformation_gradient.determinant();
-        let volume_scaled = volume * j;
-
-        let f_t: Mat2 = pp.deformation_gradient.transpose();
-        let f_inv_t = f_t.inverse();
-        let f_minus_f_inv_t = pp.deformation_gradient.sub(f_inv_t);
-
-        let p_term_0: Mat2 = f_minus_f_inv_t.mul(pp.elastic_mu);
-        let p_term_1: Mat2 = f_inv_t.mul(j.ln() * pp.elastic_lambda);
-        let p_combined: Mat2 = p_term_0.add(p_term_1);
-
-        let stress: Mat2 = p_combined.mul_mat2(&f_t).mul(1.0 / j);
-        let eq_16_term_0 = stress * (-volume_scaled * 4.0 * world.dt);
-
-        // for all surrounding 9 cells
-        for gx in 0..3 {
-            for gy in 0..3 {
-                let weight = weights[gx].x * weights[gy].y;
-                let cell_pos_x = (cell_x as i32 + gx as i32) - 1;
-                let cell_pos_y = (cell_y as i32 + gy as i32) - 1;
-                let cell_dist = Vec2::new(
-                    cell_pos_x as f32 - position.0.x + 0.5,
-                    cell_pos_y as f32 - position.0.y + 0.5,
-                );
-                let cell_at_index = grid.index_at(cell_pos_x as usize, cell_pos_y as usize);
-                // store the fused force/momentum update from MLS-MPM to apply onto grid later.
-                // todo combine into grid(x,y) = total changes as they come in here...?
-                mmc.0[gx + 3 * gy] = GridMassAndMomentumChange(
-                    cell_at_index,
-                    0.,
-                    eq_16_term_0.mul_scalar(weight).mul_vec2(cell_dist),
-                );
+                        // cells without fluid count as our own fluid so the free surface isn't an interface
+                        let cell_rest_density = if cell.fluid_volume > 0. {
+                            cell.fluid_mass / cell.fluid_volume
+                        } else {
+                            own_rest_density
+                        };
+                        rest_density_gradient += weight_gradient * cell_rest_density;
+                        rest_density_range.x = rest_density_range.x.min(cell_rest_density);
+                        rest_density_range.y = rest_density_range.y.max(cell_rest_density);
+                        interfacial_tension =
+                            interfacial_tension.min(cell.fluid_interfacial_tension);
+                    }
+                }
             }
-        }
-    });
-}
 
-pub(super) fn particles_to_grid_fluids(
-    world: Res<WorldState>,
-    grid: Res<Grid>,
-    mut particles_fluid: Query<
-        (
-            &Position,
-            &Mass,
-            &AffineMomentum,
-            &NewtonianFluidModel,
-            &mut CellMassMomentumContributions,
-        ),
-        With<ParticleTag>,
-    >,
-) {
-    let num_particles = particles_fluid.iter().count();
-    if num_particles < 1 {
-        return;
-    }
-    particles_fluid.par_for_each_mut(
-        PAR_BATCH_SIZE,
-        |(position, mass, affine_momentum, pp, mut mmc)| {
-            let cell_x: u32 = position.0.x as u32;
-            let cell_y: u32 = position.0.y as u32;
-            let cell_diff = Vec2::new(
-                position.0.x - cell_x as f32 - 0.5,
-                position.0.y - cell_y as f32 - 0.5,
-            );
-            let weights = quadratic_interpolation_weights(cell_diff);
-
-            // check surrounding 9 cells to get volume from density
-            let mut density: f32 = 0.0;
-            for gx in 0..3 {
-                for gy in 0..3 {
-                    let weight = weights[gx].x * weights[gy].y;
-                    let cell_pos_x = (cell_x as i32 + gx as i32) - 1;
-                    let cell_pos_y = (cell_y as i32 + gy as i32) - 1;
-                    let cell_at_index = grid.index_at(cell_pos_x as usize, cell_pos_y as usize);
-                    density += grid.cells[cell_at_index].mass * weight;
+            let mut stress = match rest_volume {
+                Some(_) => {
+                    let volume = mass.0 / density;
+                    let mut stress = pp.stress(density, velocity_gradient.0)
+                        + pp.surface_tension_stress(density_gradient);
+
+                    // normalize the rest density jump so the interface normal doesn't depend on the density contrast
+                    let contrast = rest_density_range.y - rest_density_range.x;
+                    if contrast > 0.01 * own_rest_density {
+                        stress += continuum_surface_stress(
+                            interfacial_tension,
+                            rest_density_gradient / contrast,
+                        );
+                    }
+                    stress * volume
                 }
+                None => {
+                    // solid stresses are kirchhoff stresses, which scale with the undeformed volume
+                    let density = mass.0 / initial_volume.0;
+                    pp.stress(density, velocity_gradient.0) * initial_volume.0
+                }
+            };
+            if let Some(damage) = damage {
+                stress = damage.degrade(stress);
             }
+            let eq_16_term_0 = stress * -world.dt;
 
-            let volume = mass.0 / density;
-
-            // fluid constitutive model
-            let pressure = f32::max(
-                -0.1,
-                pp.eos_stiffness * (f32::powf(density / pp.rest_density, pp.eos_power) - 1.0),
-            );
-            let mut stress = Mat2::from_cols(Vec2::new(-pressure, 0.0), Vec2::new(0.0, -pressure));
-            let mut strain = affine_momentum.0;
-            let trace = strain.y_axis.x + strain.x_axis.y;
-            strain.y_axis.x = trace;
-            strain.x_axis.y = trace;
-            let viscosity_term = strain * pp.dynamic_viscosity;
-            stress += viscosity_term;
-
-            let eq_16_term_0 = stress * (-volume * 4.0 * world.dt);
-
-            // for all surrounding 9 cells
-            for gx in 0..3 {
-                for gy in 0..3 {
-                    let weight = weights[gx].x * weights[gy].y;
-                    let cell_pos_x = (cell_x as i32 + gx as i32) - 1;
-                    let cell_pos_y = (cell_y as i32 + gy as i32) - 1;
-                    let cell_dist = Vec2::new(
-                        cell_pos_x as f32 - position.0.x + 0.5,
-                        cell_pos_y as f32 - position.0.y + 0.5,
+            // for all surrounding cells
+            mmc.1 = stencil.width * stencil.width;
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                    let cell_dist = grid.node_position(cell_pos_x, cell_pos_y) - position.0;
+                    let cell_at_index = grid.stencil_index(cell_pos_x, cell_pos_y);
+                    let weight_gradient =
+                        stencil.weight_gradient(&grid, world.transfer, gx, gy, cell_dist);
+                    // store the force impulse, -dt * stress * weight gradient, to apply onto grid later.
+                    // todo combine into grid(x,y) = total changes as they come in here...?
+                    mmc.0[stencil.slot(gx, gy)] = GridMassAndMomentumChange(
+                        cell_at_index,
+                        0.,
+                        eq_16_term_0.mul_vec2(weight_gradient),
+                        0.,
+                        0.,
                     );
-                    let cell_at_index = grid.index_at(cell_pos_x as usize, cell_pos_y as usize);
-                    let momentum = eq_16_term_0 * weight * cell_dist;
-                    mmc.0[gx + 3 * gy] = GridMassAndMomentumChange(cell_at_index, 0., momentum);
                 }
             }
         },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::schedule::IntoSystemDescriptor;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    use super::*;
+    use crate::defaults::FLUID_CONTACT_FIELD;
+    use crate::implicit::ImplicitParticles;
+    use crate::kernel::MAX_STENCIL_SIZE;
+    use crate::step_g2p::grid_to_particles;
+    use crate::step_update_cells::*;
+    use crate::step_update_grid::update_grid;
+
+    // fluid particles at rest filling the box from min to max, 4 to a cell
+    fn spawn_fluid(
+        world: &mut World,
+        fluid: NewtonianFluidModel,
+        min: Vec2,
+        max: Vec2,
+    ) -> Vec<Entity> {
+        let spacing = 0.5;
+        let mut particles = vec![];
+        let volume = spacing * spacing;
+        let mut y = min.y + spacing / 2.;
+        while y < max.y {
+            let mut x = min.x + spacing / 2.;
+            while x < max.x {
+                let particle = world
+                    .spawn()
+                    .insert_bundle((
+                        Position(Vec2::new(x, y)),
+                        Velocity(Vec2::ZERO),
+                        Mass(fluid.rest_density * volume),
+                        InitialVolume(volume),
+                        RestVolume(volume),
+                        InterfacialTension(fluid.interfacial_tension),
+                        AffineMomentum(Mat2::ZERO),
+                        VelocityGradient(Mat2::ZERO),
+                        CellMassMomentumContributions(
+                            [NO_MASS_AND_MOMENTUM_CHANGE; MAX_STENCIL_SIZE],
+                            0,
+                        ),
+                        ContactField(FLUID_CONTACT_FIELD),
+                        fluid,
+                        ParticleTag,
+                    ))
+                    .id();
+                particles.push(particle);
+                x += spacing;
+            }
+            y += spacing;
+        }
+        particles
+    }
+
+    fn run_system<Params>(world: &mut World, system: impl IntoSystemDescriptor<Params>) {
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(system);
+        stage.run(world);
+    }
+
+    // scatters the particle masses and then their stresses, returns the stress impulse on each cell
+    fn stress_impulses(world: &mut World) -> Vec<Vec2> {
+        ComputeTaskPool::init(TaskPool::default);
+        run_system(world, update_cells);
+        run_system(world, apply_update_cell_computations);
+        run_system(world, particles_to_grid::<NewtonianFluidModel>);
+
+        let mut impulses = vec![Vec2::ZERO; world.resource::<Grid>().cells.len()];
+        for mmc in world.query::<&CellMassMomentumContributions>().iter(world) {
+            for change in mmc.changes() {
+                impulses[change.0] += change.2;
+            }
+        }
+        impulses
+    }
+
+    #[test]
+    fn square_drop_gets_inward_surface_stress() {
+        let grid = Grid::new(Vec2::splat(16.), 1.);
+        let impulses = |surface_tension: f32| {
+            let mut world = World::new();
+            world.insert_resource(grid.clone());
+            world.insert_resource(WorldState::default());
+            let fluid = NewtonianFluidModel {
+                surface_tension,
+                ..water_properties()
+            };
+            spawn_fluid(&mut world, fluid, Vec2::splat(5.), Vec2::splat(11.));
+            stress_impulses(&mut world)
+        };
+        let with_tension = impulses(water_properties().surface_tension);
+        let without_tension = impulses(0.);
+
+        // the surface stress on each side of the drop points back towards its center
+        let center = Vec2::splat(8.);
+        let (mut left, mut right, mut bottom, mut top) =
+            (Vec2::ZERO, Vec2::ZERO, Vec2::ZERO, Vec2::ZERO);
+        for x in 0..grid.width {
+            for y in 0..grid.height {
+                let i = grid.index_at(x, y);
+                let surface_impulse = with_tension[i] - without_tension[i];
+                let offset = grid.node_position(x as i32, y as i32) - center;
+                if offset.x < 0. {
+                    left += surface_impulse;
+                } else {
+                    right += surface_impulse;
+                }
+                if offset.y < 0. {
+                    bottom += surface_impulse;
+                } else {
+                    top += surface_impulse;
+                }
+            }
+        }
+        assert!(left.x > 0., "left side pushed by {}", left);
+        assert!(right.x < 0., "right side pushed by {}", right);
+        assert!(bottom.y > 0., "bottom side pushed by {}", bottom);
+        assert!(top.y < 0., "top side pushed by {}", top);
+    }
+
+    // one full MPM step for fluid particles
+    fn step(world: &mut World) {
+        ComputeTaskPool::init(TaskPool::default);
+        run_system(world, reset_grid);
+        run_system(world, update_cells);
+        run_system(world, apply_update_cell_computations);
+        run_system(world, particles_to_grid::<NewtonianFluidModel>);
+        run_system(world, update_grid);
+        run_system(world, grid_to_particles);
+    }
+
+    // mean height of a pocket of fluid in a pool of mercury, starting at 7.5m, after a while.
+    // both are stiffened so the pool doesn't slump much under its own weight
+    fn pocket_height(pocket: NewtonianFluidModel) -> f32 {
+        let mut world = World::new();
+        world.insert_resource(Grid::new(Vec2::splat(24.), 1.));
+        world.insert_resource(WorldState::default());
+        world.init_resource::<ImplicitParticles>();
+
+        let mercury = NewtonianFluidModel {
+            eos_stiffness: 2.5e6,
+            ..mercury_properties()
+        };
+        let pocket = NewtonianFluidModel {
+            eos_stiffness: 2.5e6,
+            ..pocket
+        };
+        let pocket = spawn_fluid(&mut world, pocket, Vec2::new(10., 6.), Vec2::new(14., 9.));
+        spawn_fluid(&mut world, mercury, Vec2::new(5., 3.5), Vec2::new(19., 6.));
+        spawn_fluid(&mut world, mercury, Vec2::new(5., 6.), Vec2::new(10., 9.));
+        spawn_fluid(&mut world, mercury, Vec2::new(14., 6.), Vec2::new(19., 9.));
+        spawn_fluid(&mut world, mercury, Vec2::new(5., 9.), Vec2::new(19., 13.));
+
+        for _ in 0..150 {
+            step(&mut world);
+        }
+
+        let heights: f32 = pocket
+            .iter()
+            .map(|particle| world.get::<Position>(*particle).unwrap().0.y)
+            .sum();
+        heights / pocket.len() as f32
+    }
+
+    #[test]
+    fn light_fluid_under_a_heavy_one_rises() {
+        let water = water_properties();
+        let water_height = pocket_height(water);
+        assert!(water_height > 7.5, "water pocket sank to {}", water_height);
+
+        // a pocket as heavy as the pool settles with it instead
+        let heavy_height = pocket_height(NewtonianFluidModel {
+            rest_density: mercury_properties().rest_density,
+            ..water
+        });
+        assert!(
+            water_height > heavy_height,
+            "water pocket at {} is not above the heavy pocket at {}",
+            water_height,
+            heavy_height
+        );
+    }
+}
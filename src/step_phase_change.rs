@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+
+// particle state read and swapped when the phase flips
+type PhaseChangingParticle = (
+    Entity,
+    &'static Mass,
+    &'static ThermalProperties,
+    &'static mut Temperature,
+    &'static mut PhaseChange,
+    &'static mut Transform,
+    &'static mut Sprite,
+    Option<&'static NeoHookeanHyperElasticModel>,
+);
+
+// melt solids and freeze fluids that crossed their melting point, keeping track of latent heat.
+// the constitutive model component is swapped on the entity so the particle is picked up by the other material's systems.
+pub(super) fn update_phases(
+    mut commands: Commands,
+    mut particles: Query<PhaseChangingParticle, With<ParticleTag>>,
+) {
+    particles.for_each_mut(
+        |(
+            id,
+            mass,
+            thermal,
+            mut temperature,
+            mut phase_change,
+            mut transform,
+            mut sprite,
+            solid,
+        )| {
+            let thermal_mass = mass.0 * thermal.heat_capacity;
+            let latent_heat = phase_change.latent_heat * mass.0;
+            let is_solid = solid.is_some();
+
+            // heat above the melting point, including what is already stored as latent heat
+            let heat = phase_change.absorbed_heat
+                + (temperature.0 - phase_change.melting_point) * thermal_mass;
+
+            let freezes = heat <= 0.;
+            let melts = heat >= latent_heat;
+            if (is_solid && freezes) || (!is_solid && melts) {
+                // still fully in its current phase
+                return;
+            }
+
+            if freezes {
+                phase_change.absorbed_heat = 0.;
+                temperature.0 = phase_change.melting_point + heat / thermal_mass;
+            } else if melts {
+                phase_change.absorbed_heat = latent_heat;
+                temperature.0 = phase_change.melting_point + (heat - latent_heat) / thermal_mass;
+            } else {
+                // part way through melting or freezing, temperature holds at the melting point
+                phase_change.absorbed_heat = heat;
+                temperature.0 = phase_change.melting_point;
+                return;
+            }
+
+            // the new material starts with its own sprite size and without the damage tint
+            sprite.color = Color::WHITE;
+            if is_solid && melts {
+                // fluids have no rest shape, the deformation gradient is dropped along with the solid
+                transform.scale = Vec3::splat(NewtonianFluidModel::SPRITE_SCALE);
+                commands
+                    .entity(id)
+                    .remove::<NeoHookeanHyperElasticModel>()
+                    .remove::<Damage>()
+                    .insert_bundle((
+                        phase_change.fluid,
+                        RestVolume(mass.0 / phase_change.fluid.rest_density),
+                        InterfacialTension(phase_change.fluid.interfacial_tension),
+                        phase_change.fluid_thermal_properties,
+                        ContactField(phase_change.fluid_contact_field),
+                        phase_change.fluid_texture.clone(),
+                    ));
+            } else if !is_solid && freezes {
+                // freeze stress free in the current shape
+                transform.scale = Vec3::splat(NeoHookeanHyperElasticModel::SPRITE_SCALE);
+                commands
+                    .entity(id)
+                    .remove::<NewtonianFluidModel>()
+                    .remove::<RestVolume>()
+                    .remove::<InterfacialTension>()
+                    .insert_bundle((
+                        phase_change.solid,
+                        InitialVolume(mass.0 / phase_change.fluid.rest_density),
+                        Damage(0.),
+                        phase_change.solid_thermal_properties,
+                        ContactField(phase_change.solid_contact_field),
+                        phase_change.solid_texture.clone(),
+                    ));
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::HandleId;
+
+    use super::*;
+    use crate::defaults::{FLUID_CONTACT_FIELD, ICE_CONTACT_FIELD};
+
+    fn run_update_phases(world: &mut World) {
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(update_phases);
+        stage.run(world);
+    }
+
+    #[test]
+    fn melts_and_refreezes_with_the_matching_components() {
+        let phase_change = PhaseChange {
+            melting_point: 273.,
+            latent_heat: 334e3,
+            solid: ice_properties(),
+            fluid: water_properties(),
+            solid_thermal_properties: ice_thermal_properties(),
+            fluid_thermal_properties: water_thermal_properties(),
+            solid_texture: Handle::weak(HandleId::random::<Image>()),
+            fluid_texture: Handle::weak(HandleId::random::<Image>()),
+            solid_contact_field: ICE_CONTACT_FIELD,
+            fluid_contact_field: FLUID_CONTACT_FIELD,
+            absorbed_heat: 0.,
+        };
+        let mut world = World::new();
+        let particle = world
+            .spawn()
+            .insert_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1., 0.7, 0.7),
+                    ..default()
+                },
+                transform: Transform::from_scale(Vec3::splat(
+                    NeoHookeanHyperElasticModel::SPRITE_SCALE,
+                )),
+                texture: phase_change.solid_texture.clone(),
+                ..default()
+            })
+            .insert_bundle((
+                Mass(1.),
+                phase_change.solid,
+                Damage(0.5),
+                phase_change.solid_thermal_properties,
+                ContactField(phase_change.solid_contact_field),
+                // hot enough to absorb all the latent heat at once
+                Temperature(1000.),
+                phase_change.clone(),
+                ParticleTag,
+            ))
+            .id();
+
+        run_update_phases(&mut world);
+        let melted = world.entity(particle);
+        assert!(melted.contains::<NewtonianFluidModel>());
+        assert!(!melted.contains::<NeoHookeanHyperElasticModel>());
+        assert!(!melted.contains::<Damage>());
+        assert!(melted.contains::<RestVolume>());
+        assert!(melted.contains::<InterfacialTension>());
+        assert_eq!(
+            melted.get::<ThermalProperties>().unwrap().heat_capacity,
+            phase_change.fluid_thermal_properties.heat_capacity
+        );
+        assert_eq!(
+            melted.get::<Handle<Image>>().unwrap(),
+            &phase_change.fluid_texture
+        );
+        assert_eq!(
+            melted.get::<ContactField>().unwrap().0,
+            phase_change.fluid_contact_field
+        );
+        assert_eq!(melted.get::<Sprite>().unwrap().color, Color::WHITE);
+        assert_eq!(
+            melted.get::<Transform>().unwrap().scale,
+            Vec3::splat(NewtonianFluidModel::SPRITE_SCALE)
+        );
+        assert!(melted.get::<Temperature>().unwrap().0 > phase_change.melting_point);
+
+        world.get_mut::<Temperature>(particle).unwrap().0 = 0.;
+        run_update_phases(&mut world);
+        let frozen = world.entity(particle);
+        assert!(frozen.contains::<NeoHookeanHyperElasticModel>());
+        assert!(!frozen.contains::<NewtonianFluidModel>());
+        assert!(!frozen.contains::<RestVolume>());
+        assert!(!frozen.contains::<InterfacialTension>());
+        assert_eq!(frozen.get::<Damage>().unwrap().0, 0.);
+        assert_eq!(
+            frozen.get::<InitialVolume>().unwrap().0,
+            1. / phase_change.fluid.rest_density
+        );
+        assert_eq!(
+            frozen.get::<ThermalProperties>().unwrap().heat_capacity,
+            phase_change.solid_thermal_properties.heat_capacity
+        );
+        assert_eq!(
+            frozen.get::<Handle<Image>>().unwrap(),
+            &phase_change.solid_texture
+        );
+        assert_eq!(
+            frozen.get::<ContactField>().unwrap().0,
+            phase_change.solid_contact_field
+        );
+        assert_eq!(
+            frozen.get::<Transform>().unwrap().scale,
+            Vec3::splat(NeoHookeanHyperElasticModel::SPRITE_SCALE)
+        );
+        assert!(frozen.get::<Temperature>().unwrap().0 < phase_change.melting_point);
+    }
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::grid::Grid;
+use crate::world::*;
+
+// move rigid bodies with the momentum they took from the grid this step, plus gravity.
+pub(super) fn integrate_rigid_bodies(
+    world: Res<WorldState>,
+    grid: Res<Grid>,
+    mut bodies: Query<(&mut RigidBody, &mut Transform)>,
+) {
+    let gravity = if world.gravity_enabled {
+        world.gravity
+    } else {
+        0.
+    };
+
+    bodies.for_each_mut(|(mut body, mut transform)| {
+        let body = &mut *body;
+        body.velocity += body.impulse / body.mass;
+        body.velocity.y += world.dt * gravity;
+        body.angular_velocity += body.angular_impulse / body.inertia;
+        body.impulse = Vec2::ZERO;
+        body.angular_impulse = 0.;
+        if body.pinned {
+            body.velocity = Vec2::ZERO;
+        }
+
+        body.position += body.velocity * world.dt;
+        body.rotation += body.angular_velocity * world.dt;
+
+        // keep the whole body inside the walls, pinned bodies stop rotating instead
+        let (sin, cos) = body.rotation.sin_cos();
+        let extent = Vec2::new(
+            cos.abs() * body.half_extents.x + sin.abs() * body.half_extents.y,
+            sin.abs() * body.half_extents.x + cos.abs() * body.half_extents.y,
+        );
+        let thickness = grid.boundaries.thickness as f32;
+        let wall_min = Vec2::splat((thickness + 1.) * grid.dx) + extent;
+        let wall_max = grid.domain_size() - Vec2::splat((thickness + 2.) * grid.dx) - extent;
+        let inside = body.position.clamp(wall_min, wall_max);
+        if inside != body.position {
+            if body.pinned {
+                body.rotation -= body.angular_velocity * world.dt;
+                body.angular_velocity = 0.;
+            } else {
+                if inside.x != body.position.x {
+                    body.velocity.x = 0.;
+                }
+                if inside.y != body.position.y {
+                    body.velocity.y = 0.;
+                }
+                body.position = inside;
+            }
+        }
+
+        // drawn in grid cells like the particles
+        transform.translation.x = body.position.x * grid.inv_dx();
+        transform.translation.y = body.position.y * grid.inv_dx();
+        transform.rotation = Quat::from_rotation_z(body.rotation);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::IntoSystemDescriptor;
+
+    fn run_system<Params>(world: &mut World, system: impl IntoSystemDescriptor<Params>) {
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(system);
+        stage.run(world);
+    }
+
+    fn fall_onto_floor(thickness: usize) -> RigidBody {
+        let mut grid = Grid::new(Vec2::splat(16.), 1.);
+        grid.boundaries.thickness = thickness;
+        let mut state = WorldState::default();
+        state.dt = 0.1;
+        state.gravity_enabled = false;
+
+        let mut world = World::new();
+        world.insert_resource(grid);
+        world.insert_resource(state);
+        let mut body = RigidBody::new_box(Vec2::new(8., 2.), Vec2::ONE, 100., false);
+        body.velocity = Vec2::new(1., -5.);
+        let entity = world
+            .spawn()
+            .insert_bundle((body, Transform::default()))
+            .id();
+
+        run_system(&mut world, integrate_rigid_bodies);
+        *world.get::<RigidBody>(entity).unwrap()
+    }
+
+    #[test]
+    fn walls_stop_bodies_at_the_boundary_thickness() {
+        for thickness in [2, 4] {
+            let body = fall_onto_floor(thickness);
+            let floor = (thickness + 1) as f32 + body.half_extents.y;
+            assert!(
+                (body.position.y - floor).abs() < 1e-5,
+                "body at {} instead of {} with walls {} cells thick",
+                body.position.y,
+                floor,
+                thickness
+            );
+            assert_eq!(body.velocity.y, 0.);
+            // sliding along the floor is left alone
+            assert_eq!(body.velocity.x, 1.);
+        }
+    }
+}
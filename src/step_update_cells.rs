@@ -4,18 +4,20 @@ use crate::components::*;
 use crate::defaults::*;
 use crate::grid::*;
 
+// particle state scattered onto the grid, rest volume and interfacial tension only exist on fluids
+type ScatteredParticle = (
+    &'static Position,
+    &'static Velocity,
+    &'static Mass,
+    &'static AffineMomentum,
+    Option<&'static RestVolume>,
+    Option<&'static InterfacialTension>,
+    &'static mut CellMassMomentumContributions,
+);
+
 pub(super) fn update_cells(
     grid: Res<Grid>,
-    mut particles: Query<
-        (
-            &Position,
-            &Velocity,
-            &Mass,
-            &AffineMomentum,
-            &mut CellMassMomentumContributions,
-        ),
-        With<ParticleTag>,
-    >,
+    mut particles: Query<ScatteredParticle, With<ParticleTag>>,
 ) {
     let num_particles = particles.iter().count();
     if num_particles < 1 {
@@ -23,34 +25,28 @@ pub(super) fn update_cells(
     }
     particles.par_for_each_mut(
         PAR_BATCH_SIZE,
-        |(position, velocity, mass, affine_momentum, mut mmc)| {
-            let cell_x: u32 = position.0.x as u32;
-            let cell_y: u32 = position.0.y as u32;
-            let cell_diff = Vec2::new(
-                position.0.x - cell_x as f32 - 0.5,
-                position.0.y - cell_y as f32 - 0.5,
-            );
-            let weights = quadratic_interpolation_weights(cell_diff);
+        |(position, velocity, mass, affine_momentum, rest_volume, interfacial_tension, mut mmc)| {
+            let stencil = grid.kernel.stencil(&grid, position.0);
 
-            //collect momentum changes for surrounding 9 cells.
-            for gx in 0..3 {
-                for gy in 0..3 {
-                    let weight = weights[gx].x * weights[gy].y;
-                    let cell_pos_x = (cell_x as i32 + gx as i32) - 1;
-                    let cell_pos_y = (cell_y as i32 + gy as i32) - 1;
-                    let cell_dist = Vec2::new(
-                        cell_pos_x as f32 - position.0.x + 0.5,
-                        cell_pos_y as f32 - position.0.y + 0.5,
-                    );
-                    let cell_at_index = grid.index_at(cell_pos_x as usize, cell_pos_y as usize);
+            //collect momentum changes for surrounding cells.
+            mmc.1 = stencil.width * stencil.width;
+            for gx in 0..stencil.width {
+                for gy in 0..stencil.width {
+                    let weight = stencil.weight(gx, gy);
+                    let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                    let cell_dist = grid.node_position(cell_pos_x, cell_pos_y) - position.0;
+                    let cell_at_index = grid.stencil_index(cell_pos_x, cell_pos_y);
 
                     let q = affine_momentum.0 * cell_dist;
                     let mass_contrib = weight * mass.0;
+                    let volume_contrib = rest_volume.map_or(0., |v| weight * v.0);
                     // mass and momentum update
-                    mmc.0[gx + 3 * gy] = GridMassAndMomentumChange(
+                    mmc.0[stencil.slot(gx, gy)] = GridMassAndMomentumChange(
                         cell_at_index,
                         mass_contrib,
                         (velocity.0 + q) * mass_contrib,
+                        volume_contrib,
+                        interfacial_tension.map_or(0., |t| t.0),
                     );
                 }
             }
@@ -62,12 +58,20 @@ pub(super) fn update_cells(
 // .. after this one is done https://github.com/bevyengine/bevy/issues/2648
 pub(super) fn apply_update_cell_computations(
     mut grid: ResMut<Grid>,
-    particles: Query<(&CellMassMomentumContributions,), With<ParticleTag>>,
+    particles: Query<(&CellMassMomentumContributions, &ContactField), With<ParticleTag>>,
 ) {
-    particles.for_each(|mmc| {
-        for change in mmc.0 .0.iter() {
-            grid.cells[change.0].mass += change.1;
-            grid.cells[change.0].velocity += change.2;
+    particles.for_each(|(mmc, field)| {
+        for change in mmc.changes() {
+            let cell = &mut grid.cells[change.0];
+            cell.mass += change.1;
+            cell.velocity += change.2;
+            cell.field_mass[field.0] += change.1;
+            cell.field_velocity[field.0] += change.2;
+            if change.3 > 0. {
+                cell.fluid_mass += change.1;
+                cell.fluid_volume += change.3;
+                cell.fluid_interfacial_tension = cell.fluid_interfacial_tension.min(change.4);
+            }
         }
     });
 }
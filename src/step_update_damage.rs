@@ -0,0 +1,30 @@
+use bevy::prelude::*;
+
+use crate::components::*;
+use crate::defaults::*;
+use crate::linalg::*;
+
+// accumulate damage in breakable solids from their tensile strain energy. damage never heals.
+pub(super) fn update_damage(
+    mut particles_solid: Query<(&NeoHookeanHyperElasticModel, &mut Damage), With<ParticleTag>>,
+) {
+    particles_solid.par_for_each_mut(PAR_BATCH_SIZE, |(pp, mut damage)| {
+        let fracture_toughness = match pp.fracture_toughness {
+            Some(fracture_toughness) if damage.0 < 1. => fracture_toughness,
+            _ => return,
+        };
+
+        // tensile part of the Hencky strain energy density
+        let (_, sigma, _) = svd(pp.deformation_gradient);
+        let log_strain = Vec2::new(sigma.x.abs().max(1e-4).ln(), sigma.y.abs().max(1e-4).ln());
+        let tensile_strain = log_strain.max(Vec2::ZERO);
+        let tensile_trace = f32::max(0., log_strain.x + log_strain.y);
+        let tensile_energy = pp.elastic_mu * tensile_strain.length_squared()
+            + 0.5 * pp.elastic_lambda * tensile_trace * tensile_trace;
+
+        let damage_new = ((tensile_energy - fracture_toughness) / fracture_toughness).clamp(0., 1.);
+        if damage_new > damage.0 {
+            damage.0 = damage_new;
+        }
+    });
+}
@@ -1,21 +1,34 @@
 use bevy::prelude::*;
 
 use crate::components::*;
-use crate::grid::Grid;
+use crate::grid::*;
+use crate::implicit::*;
+use crate::kernel::MAX_STENCIL_SIZE;
 use crate::world::*;
 
 pub(super) fn update_grid(
     mut grid: ResMut<Grid>,
     mut world: ResMut<WorldState>,
-    particles: Query<(&CellMassMomentumContributions,), With<ParticleTag>>,
+    mut implicit: ResMut<ImplicitParticles>,
+    particles: Query<(&CellMassMomentumContributions, &ContactField), With<ParticleTag>>,
+    mut bodies: Query<(Entity, &mut RigidBody)>,
+    colliders: Query<(&Collider, Option<&KinematicMotion>)>,
 ) {
-    particles.for_each(|mmc| {
-        for change in mmc.0 .0.iter() {
-            grid.cells[change.0].velocity += change.2;
+    if world.transfer == TransferScheme::PicFlip {
+        grid.store_previous_velocities();
+    }
+    particles.for_each(|(mmc, field)| {
+        for change in mmc.changes() {
+            let cell = &mut grid.cells[change.0];
+            cell.velocity += change.2;
+            cell.field_velocity[field.0] += change.2;
         }
     });
 
     world.update();
+    let (ids, mut rigid_bodies): (Vec<Entity>, Vec<RigidBody>) =
+        bodies.iter().map(|(id, body)| (id, *body)).unzip();
+    let colliders: Vec<(&Collider, Option<&KinematicMotion>)> = colliders.iter().collect();
     grid.update(
         world.dt,
         if world.gravity_enabled {
@@ -23,5 +36,57 @@ pub(super) fn update_grid(
         } else {
             0.
         },
+        world.implicit.then(|| implicit.0.as_slice()),
+        world.multi_field_contact.then_some(world.contact_friction),
+        &mut rigid_bodies,
+        &colliders,
     );
+    implicit.0.clear();
+    for (id, updated) in ids.into_iter().zip(rigid_bodies) {
+        if let Ok((_, mut body)) = bodies.get_mut(id) {
+            *body = updated;
+        }
+    }
+}
+
+// collect particles of material M for the implicit solve, with their stencils and stress differentials
+pub(super) fn gather_implicit_particles<M: ConstitutiveModel>(
+    world: Res<WorldState>,
+    grid: Res<Grid>,
+    mut implicit: ResMut<ImplicitParticles>,
+    particles: Query<(&Position, &InitialVolume, &M, Option<&Damage>), With<ParticleTag>>,
+) {
+    if !world.implicit {
+        return;
+    }
+    particles.for_each(|(position, initial_volume, pp, damage)| {
+        // fully damaged particles only resist compression, they stay explicit
+        let stiffness = match damage {
+            Some(damage) if damage.0 >= 1. => return,
+            Some(damage) => f32::powi(1. - damage.0, 2),
+            None => 1.,
+        };
+
+        let stencil = grid.kernel.stencil(&grid, position.0);
+
+        let mut cells = [(0, Vec2::ZERO); MAX_STENCIL_SIZE];
+        for gx in 0..stencil.width {
+            for gy in 0..stencil.width {
+                let (cell_pos_x, cell_pos_y) = stencil.cell(gx, gy);
+                let cell_dist = grid.node_position(cell_pos_x, cell_pos_y) - position.0;
+                cells[stencil.slot(gx, gy)] = (
+                    grid.stencil_index(cell_pos_x, cell_pos_y),
+                    stencil.weight_gradient(&grid, world.transfer, gx, gy, cell_dist),
+                );
+            }
+        }
+
+        implicit.0.push(ImplicitParticle {
+            volume: initial_volume.0,
+            stress_differentials: unit_deformation_steps()
+                .map(|step| pp.stress_differential(step) * stiffness),
+            stencil: cells,
+            stencil_size: stencil.width * stencil.width,
+        });
+    });
 }
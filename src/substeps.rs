@@ -0,0 +1,154 @@
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+
+use crate::grid::Grid;
+use crate::step_adaptive_dt::choose_time_step;
+use crate::world::*;
+
+// stage holding the whole MPM pipeline, run zero or more times per rendered frame
+pub(super) const SIMULATION_STAGE: &str = "simulation";
+
+#[derive(Default)]
+pub(super) struct SubstepClock {
+    // simulated time owed to the simulation, in seconds
+    accumulator: f32,
+    substeps: usize,
+    running: bool,
+}
+
+// run criteria for the simulation stage.
+// each frame banks wall time * simulation_speed, then runs steps of world.dt until it is spent.
+// adaptive time stepping picks each step's dt here, before checking there is enough time banked for it.
+// frames that would need more than max_substeps drop the rest, so a slow machine runs in slow motion
+// instead of falling further behind every frame.
+pub(super) fn run_substeps(
+    time: Res<Time>,
+    mut world: ResMut<WorldState>,
+    grid: Res<Grid>,
+    mut clock: Local<SubstepClock>,
+) -> ShouldRun {
+    if clock.running {
+        clock.accumulator -= world.dt;
+    } else {
+        clock.accumulator += time.delta_seconds() * world.simulation_speed;
+        clock.substeps = 0;
+        clock.running = true;
+    }
+
+    choose_time_step(&mut world, grid.dx);
+    if clock.accumulator >= world.dt && clock.substeps < world.max_substeps {
+        clock.substeps += 1;
+        // the step about to run measures the signal speed for the one after it
+        world.max_signal_speed = 0.;
+        return ShouldRun::YesAndCheckAgain;
+    }
+
+    if clock.substeps >= world.max_substeps {
+        clock.accumulator = 0.;
+    }
+    world.substeps = clock.substeps;
+    clock.running = false;
+    ShouldRun::No
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::utils::{Duration, Instant};
+
+    // drives run_substeps like the stage does, one rendered frame at a time
+    struct Frames {
+        world: World,
+        criteria: Box<dyn System<In = (), Out = ShouldRun>>,
+        now: Instant,
+    }
+
+    impl Frames {
+        fn new(dt: f32, max_substeps: usize, simulation_speed: f32) -> Self {
+            let mut state = WorldState::default();
+            state.dt = dt;
+            state.max_substeps = max_substeps;
+            state.simulation_speed = simulation_speed;
+            let now = Instant::now();
+            let mut time = Time::default();
+            time.update_with_instant(now);
+
+            let mut world = World::new();
+            world.insert_resource(state);
+            world.insert_resource(time);
+            world.insert_resource(Grid::new(Vec2::splat(16.), 1.));
+            let mut criteria: Box<dyn System<In = (), Out = ShouldRun>> =
+                Box::new(IntoSystem::into_system(run_substeps));
+            criteria.initialize(&mut world);
+            Frames {
+                world,
+                criteria,
+                now,
+            }
+        }
+
+        // steps run in a frame that took this long
+        fn frame(&mut self, seconds: f32) -> usize {
+            self.now += Duration::from_secs_f32(seconds);
+            let now = self.now;
+            self.world.resource_mut::<Time>().update_with_instant(now);
+            let mut steps = 0;
+            while self.criteria.run((), &mut self.world) == ShouldRun::YesAndCheckAgain {
+                steps += 1;
+            }
+            assert_eq!(self.world.resource::<WorldState>().substeps, steps);
+            steps
+        }
+    }
+
+    // times are multiples of 1/128s so they add up exactly
+    const DT: f32 = 1. / 64.;
+
+    #[test]
+    fn leftover_time_carries_over_to_the_next_frame() {
+        let mut frames = Frames::new(DT, 10, 1.);
+        assert_eq!(frames.frame(3.5 * DT), 3);
+        assert_eq!(frames.frame(0.5 * DT), 1);
+        assert_eq!(frames.frame(0.5 * DT), 0);
+        assert_eq!(frames.frame(1.5 * DT), 2);
+    }
+
+    #[test]
+    fn simulation_speed_scales_the_time_banked() {
+        let mut frames = Frames::new(DT, 10, 0.5);
+        assert_eq!(frames.frame(4. * DT), 2);
+        assert_eq!(frames.frame(1. * DT), 0);
+        assert_eq!(frames.frame(1. * DT), 1);
+    }
+
+    #[test]
+    fn slow_frames_drop_time_past_max_substeps() {
+        let mut frames = Frames::new(DT, 4, 1.);
+        assert_eq!(frames.frame(10. * DT), 4);
+        // the six steps that didn't fit are gone, not run next frame
+        assert_eq!(frames.frame(0.5 * DT), 0);
+        assert_eq!(frames.frame(0.5 * DT), 1);
+    }
+
+    #[test]
+    fn adaptive_steps_only_run_once_their_time_is_banked() {
+        let mut frames = Frames::new(DT, 10, 1.);
+        {
+            let mut world = frames.world.resource_mut::<WorldState>();
+            world.adaptive_dt = true;
+            world.cfl = 0.5;
+            world.min_dt = DT / 8.;
+            world.max_dt = 2. * DT;
+        }
+        // nothing measured yet, the next step is max_dt long
+        assert_eq!(frames.frame(1.5 * DT), 0);
+        assert_eq!(frames.world.resource::<WorldState>().dt, 2. * DT);
+        assert_eq!(frames.frame(0.5 * DT), 1);
+
+        // a signal crossing half a cell per half step, kept until a step runs
+        frames.world.resource_mut::<WorldState>().max_signal_speed = 0.5 / (0.5 * DT);
+        assert_eq!(frames.frame(0.25 * DT), 0);
+        // only a half step fits in the time banked, a max_dt step would not have run
+        assert_eq!(frames.frame(0.25 * DT), 1);
+    }
+}
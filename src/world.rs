@@ -1,11 +1,48 @@
 use super::defaults::*;
 
+// how velocities move between particles and the grid
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(super) enum TransferScheme {
+    // particles take the interpolated grid velocity, stable but damps rotation and small scale motion
+    Pic,
+    // particles keep their own velocity plus the change of the grid velocity, blended with PIC by flip_ratio
+    PicFlip,
+    // PIC plus an affine velocity field per particle (Jiang et al. 2015),
+    // forces and the velocity gradient come from the B-spline weight gradients
+    Apic,
+    // APIC where the affine velocity also stands in for the weight gradients (Hu et al. 2018)
+    MlsMpm,
+}
+
 #[derive(Copy, Clone)]
 pub(super) struct WorldState {
     pub(super) dt: f32,
+    // pick dt each step from the CFL condition instead of using the fixed value
+    pub(super) adaptive_dt: bool,
+    // fraction of a cell the fastest wave may cross in one adaptive step
+    pub(super) cfl: f32,
+    pub(super) min_dt: f32,
+    pub(super) max_dt: f32,
+    // fastest particle plus wave speed this step, gathered for the adaptive dt
+    pub(super) max_signal_speed: f32,
+    // integrate the elastic forces of hyperelastic solids implicitly, so stiff solids take larger steps
+    pub(super) implicit: bool,
+    pub(super) transfer: TransferScheme,
+    // share of FLIP in the PIC/FLIP blend, 1 is pure FLIP
+    pub(super) flip_ratio: f32,
     pub(super) gravity: f32,
     pub(super) gravity_enabled: bool,
     pub(super) current_tick: usize,
+    // simulated seconds per wall clock second, steps of dt are run each frame to keep up
+    pub(super) simulation_speed: f32,
+    // most steps run in one frame
+    pub(super) max_substeps: usize,
+    // steps run last frame
+    pub(super) substeps: usize,
+    // give each contact field its own grid velocity so materials can slide and separate
+    pub(super) multi_field_contact: bool,
+    // coulomb friction coefficient between contact fields
+    pub(super) contact_friction: f32,
 }
 
 impl WorldState {
@@ -16,9 +53,22 @@ impl WorldState {
     pub(super) fn default() -> WorldState {
         WorldState {
             dt: DEFAULT_DT,
+            adaptive_dt: false,
+            cfl: DEFAULT_CFL,
+            min_dt: DEFAULT_MIN_DT,
+            max_dt: DEFAULT_MAX_DT,
+            max_signal_speed: 0.,
+            implicit: false,
+            transfer: TransferScheme::MlsMpm,
+            flip_ratio: DEFAULT_FLIP_RATIO,
             gravity: DEFAULT_GRAVITY,
             gravity_enabled: true,
             current_tick: 0,
+            simulation_speed: DEFAULT_SIMULATION_SPEED,
+            max_substeps: DEFAULT_MAX_SUBSTEPS,
+            substeps: 0,
+            multi_field_contact: false,
+            contact_friction: DEFAULT_CONTACT_FRICTION,
         }
     }
 